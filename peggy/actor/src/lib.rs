@@ -1,6 +1,4 @@
 use anyhow::{anyhow, Error as AnyhowError};
-use base64;
-use cid::Cid;
 use fil_actor_init::{ExecParams, ExecReturn as ExecReturn1, InstallParams, InstallReturn as InstallReturn1};
 use fil_actors_runtime::INIT_ACTOR_ADDR;
 use forest_json::cid::CidJson;
@@ -10,10 +8,11 @@ use fvm_ipld_encoding_3::{
     Cbor,
     RawBytes,
 };
-use fvm_shared::{address::Address, econ::TokenAmount};
-use log::{info, warn};
+use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode, message::Message};
+use lotusapi::json::address;
 use serde::{Deserialize, Serialize};
 use std::{
+    fmt,
     path::PathBuf,
     process::{Command, Stdio},
     str::FromStr,
@@ -21,9 +20,10 @@ use std::{
 };
 use thiserror::Error;
 
-use mpool::{mpool_push, MpoolError};
+use mpool::{mpool_build, mpool_dry_run, mpool_push, wait_msg_bumped, BumpPolicy, MpoolError, Simulation};
+use msig::{propose, InnerMessage, MsigError, ProposeReturn};
 use rpc::RpcEndpoint;
-use state::{wait_msg, StateError};
+use state::{decode_cbor, decode_json, replay_msg, wait_msg, ExecutionTrace, ReturnDecode, StateError, WaitOptions};
 
 #[derive(Debug, Error)]
 pub enum ActorError {
@@ -39,14 +39,16 @@ pub enum ActorError {
     MpoolCallError(#[from] MpoolError),
     #[error("state call error: {0}")]
     StateCallError(#[from] StateError),
-    #[error("decode base64 error: {0}")]
-    DecodeBase64Error(#[from] base64::DecodeError),
     #[error("decode ipld error: {0}")]
     DecodeIpldError(#[from] fvm_ipld_encoding_3::Error),
     #[error("parse address error")]
     ParseAddressError(#[from] fvm_shared::address::Error),
     #[error("clone fvm repo error code {0}")]
     CloneFVMRepoError(std::process::ExitStatus),
+    #[error("message code error: {0}\n{1}")]
+    MsgTraceError(ExitCode, ExecutionTrace),
+    #[error("msig call error: {0}")]
+    MsigCallError(#[from] MsigError),
 }
 
 pub fn clone_actor(repo_url: &str, repo_rev: &str, target_path: PathBuf) -> Result<(), ActorError> {
@@ -95,7 +97,9 @@ pub fn compile_actor(target_path: PathBuf) -> Result<PathBuf, ActorError> {
     Ok(wasm_path)
 }
 
+// Lotus renders ReturnDec with the go field names, like every other return decoded from json
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
 pub struct InstallReturn {
     pub code_cid: CidJson,
     pub installed: bool,
@@ -112,13 +116,15 @@ impl FromStr for InstallReturn {
     }
 }
 
-impl Default for InstallReturn {
-    fn default() -> Self {
-        Self {
-            code_cid: CidJson(
-                Cid::from_str("bafyreibjo4xmgaevkgud7mbifn3dzp4v4lyaui4yvqp3f2bqwtxcjrdqg4").unwrap() as Cid
-            ),
-            installed: false,
+impl ReturnDecode for InstallReturn {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        decode_json(ret)
+    }
+
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        match RawBytes::deserialize::<InstallReturn1>(&RawBytes::new(ret.to_vec())) {
+            Ok(ret) => Ok(Self { code_cid: CidJson(ret.code_cid), installed: ret.installed }),
+            Err(err) => Err(StateError::DecodeReturnCborError(err.to_string())),
         }
     }
 }
@@ -133,37 +139,39 @@ pub async fn install_actor(
     let code = RawBytes::from(code);
     let params = InstallParams { code };
 
-    match mpool_push::<_, CidJson>(
-        rpc.clone(),
-        from,
-        from_key_info,
-        INIT_ACTOR_ADDR,
-        4,
-        TokenAmount::from_atto(0),
-        params,
-    )
-    .await
-    {
-        Ok(res) => match wait_msg::<InstallReturn>(rpc, res.clone()).await {
+    match mpool_push(rpc.clone(), from, from_key_info, INIT_ACTOR_ADDR, 4, TokenAmount::from_atto(0), params).await {
+        Ok(res) => match wait_msg::<_, InstallReturn>(rpc, res.clone()).await {
             Ok(ret) => Ok((ret.code_cid, ret.installed)),
-            Err(StateError::ParseByYourSelf(s)) => {
-                warn!("> State cannot parse {}, parse by youself!", &s);
-                let s = base64::decode_config(&s, base64::STANDARD)?;
-                let b = RawBytes::new(s);
-                let ret: InstallReturn1 = RawBytes::deserialize(&b)?;
-                Ok((CidJson(ret.code_cid), ret.installed))
-            }
             Err(err) => Err(ActorError::StateCallError(err)),
         },
         Err(err) => Err(ActorError::MpoolCallError(err)),
     }
 }
 
-#[derive(Serialize, Deserialize, Default)]
+// The code cid is only known when the node returns InstallReturn as raw cbor
+pub async fn simulate_install_actor(
+    rpc: RpcEndpoint,
+    from: Address,
+    target_path: PathBuf,
+) -> Result<(Simulation, Option<(CidJson, bool)>), ActorError> {
+    let code = std::fs::read(target_path)?;
+    let params = InstallParams { code: RawBytes::from(code) };
+
+    match mpool_dry_run(rpc, from, INIT_ACTOR_ADDR, 4, TokenAmount::from_atto(0), params).await {
+        Ok(simulation) => {
+            let ret = serde_json::from_value::<(CidJson, bool)>(simulation.return_value.clone()).ok();
+            Ok((simulation, ret))
+        }
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ExecReturn {
-    #[serde(rename = "IDAddress")]
+    #[serde(rename = "IDAddress", with = "address")]
     pub id_address: Address,
+    #[serde(with = "address")]
     pub robust_address: Address,
 }
 
@@ -171,20 +179,27 @@ impl FromStr for ExecReturn {
     type Err = ActorError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        #[derive(Deserialize)]
-        #[serde(rename_all = "PascalCase")]
-        struct T {
-            #[serde(rename = "IDAddress")]
-            id_address: String,
-            robust_address: String,
+        match serde_json::from_str::<Self>(s) {
+            Ok(ret) => Ok(ret),
+            Err(err) => Err(ActorError::ParseJsonError(err)),
         }
+    }
+}
 
-        let v = serde_json::from_str::<T>(s)?;
-
-        let id_address = Address::from_str(&v.id_address)?;
-        let robust_address = Address::from_str(&v.robust_address)?;
+impl ReturnDecode for ExecReturn {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        decode_json(ret)
+    }
 
-        Ok(Self { id_address, robust_address })
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        let ret = match RawBytes::deserialize::<ExecReturn1>(&RawBytes::new(ret.to_vec())) {
+            Ok(ret) => ret,
+            Err(err) => return Err(StateError::DecodeReturnCborError(err.to_string())),
+        };
+        Ok(Self {
+            id_address: Address::from_str(&ret.id_address.to_string())?,
+            robust_address: Address::from_str(&ret.robust_address.to_string())?,
+        })
     }
 }
 
@@ -197,33 +212,46 @@ pub async fn create_actor(
     let CidJson(_cid) = actor_code_id;
     let params = ExecParams { code_cid: _cid, constructor_params: RawBytes::new(Vec::new()) };
 
-    match mpool_push::<_, CidJson>(
+    match mpool_push(rpc.clone(), from, from_key_info, INIT_ACTOR_ADDR, 2, TokenAmount::from_atto(0), params).await {
+        Ok(res) => match wait_msg::<_, ExecReturn>(rpc, res.clone()).await {
+            Ok(ret) => Ok((ret.id_address, ret.robust_address)),
+            Err(err) => Err(ActorError::StateCallError(err)),
+        },
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+// Exec runs against the head state, the code has to be installed already
+pub async fn simulate_create_actor(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_code_id: CidJson,
+) -> Result<Simulation, ActorError> {
+    let CidJson(code_cid) = actor_code_id;
+    let params = ExecParams { code_cid, constructor_params: RawBytes::new(Vec::new()) };
+
+    match mpool_dry_run(rpc, from, INIT_ACTOR_ADDR, 2, TokenAmount::from_atto(0), params).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+// A failed owner actor call only reports its own exit code, replay it to find the failed internal send
+// Custody messages are bumped while stuck, StateReplay follows the replacement to the executed message
+async fn wait_msg_traced(rpc: RpcEndpoint, from_key_info: KeyInfo, cid: CidJson) -> Result<(), ActorError> {
+    match wait_msg_bumped::<_, ()>(
         rpc.clone(),
-        from,
         from_key_info,
-        INIT_ACTOR_ADDR,
-        2,
-        TokenAmount::from_atto(0),
-        params,
+        cid.clone(),
+        WaitOptions::default(),
+        BumpPolicy::default(),
     )
     .await
     {
-        Ok(res) => match wait_msg::<ExecReturn>(rpc, res.clone()).await {
-            Ok(ret) => Ok((
-                Address::from_str(&ret.id_address.to_string())?,
-                Address::from_str(&ret.robust_address.to_string())?,
-            )),
-            Err(StateError::ParseByYourSelf(s)) => {
-                warn!("> State cannot parse {}, parse by youself!", &s);
-                let s = base64::decode_config(&s, base64::STANDARD)?;
-                let b = RawBytes::new(s);
-                let ret: ExecReturn1 = RawBytes::deserialize(&b)?;
-                Ok((
-                    Address::from_str(&ret.id_address.to_string())?,
-                    Address::from_str(&ret.robust_address.to_string())?,
-                ))
-            }
-            Err(err) => Err(ActorError::StateCallError(err)),
+        Ok(_) => Ok(()),
+        Err(MpoolError::StateCallError(StateError::MsgCodeError(exit_code))) => match replay_msg(rpc, cid).await {
+            Ok(trace) => Err(ActorError::MsgTraceError(exit_code, trace)),
+            Err(_) => Err(ActorError::StateCallError(StateError::MsgCodeError(exit_code))),
         },
         Err(err) => Err(ActorError::MpoolCallError(err)),
     }
@@ -236,23 +264,56 @@ pub async fn take_owner(
     actor_id: Address,
     miner_id: Address,
 ) -> Result<(), ActorError> {
-    match mpool_push::<_, CidJson>(rpc.clone(), from, from_key_info, actor_id, 16, TokenAmount::from_atto(0), miner_id)
-        .await
+    match mpool_push(rpc.clone(), from, from_key_info.clone(), actor_id, 16, TokenAmount::from_atto(0), miner_id).await
     {
-        Ok(res) => match wait_msg::<serde_json::Value>(rpc, res).await {
-            Ok(_) => Ok(()),
-            Err(StateError::ParseByYourSelf(s)) => {
-                warn!("> State cannot parse {}, parse by youself!", &s);
-                let s = base64::decode_config(&s, base64::STANDARD)?;
-                info!("> {}", String::from_utf8(s)?);
-                Ok(())
-            }
-            Err(err) => Err(ActorError::StateCallError(err)),
-        },
+        Ok(res) => wait_msg_traced(rpc, from_key_info, res).await,
+        Err(MpoolError::SimulationFailed(simulation)) => {
+            Err(ActorError::MsgTraceError(simulation.exit_code, simulation.trace))
+        }
         Err(err) => Err(ActorError::MpoolCallError(err)),
     }
 }
 
+// Unsigned counterpart of take_owner for an owner key kept offline
+pub async fn build_take_owner(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+) -> Result<Message, ActorError> {
+    match mpool_build(rpc, from, actor_id, 16, TokenAmount::from_atto(0), miner_id).await {
+        Ok(msg) => Ok(msg),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn simulate_take_owner(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+) -> Result<Simulation, ActorError> {
+    match mpool_dry_run(rpc, from, actor_id, 16, TokenAmount::from_atto(0), miner_id).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn propose_take_owner(
+    rpc: RpcEndpoint,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    actor_id: Address,
+    miner_id: Address,
+) -> Result<ProposeReturn, ActorError> {
+    let inner = InnerMessage::new(actor_id, TokenAmount::from_atto(0), 16, miner_id)?;
+    match propose(rpc, signer, signer_key_info, msig, inner).await {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(ActorError::MsigCallError(err)),
+    }
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Default)]
 struct ChangeWorkerParams {
     miner_id: Address,
@@ -270,23 +331,62 @@ pub async fn change_worker(
 ) -> Result<(), ActorError> {
     let params = ChangeWorkerParams { miner_id, new_worker_id };
 
-    match mpool_push::<_, CidJson>(rpc.clone(), from, from_key_info, actor_id, 18, TokenAmount::from_atto(0), params)
-        .await
-    {
-        Ok(res) => match wait_msg::<serde_json::Value>(rpc, res).await {
-            Ok(_) => Ok(()),
-            Err(StateError::ParseByYourSelf(s)) => {
-                warn!("> State cannot parse {}, parse by youself!", &s);
-                let s = base64::decode_config(&s, base64::STANDARD)?;
-                info!("> {}", String::from_utf8(s)?);
-                Ok(())
-            }
-            Err(err) => Err(ActorError::StateCallError(err)),
-        },
+    match mpool_push(rpc.clone(), from, from_key_info.clone(), actor_id, 18, TokenAmount::from_atto(0), params).await {
+        Ok(res) => wait_msg_traced(rpc, from_key_info, res).await,
+        Err(MpoolError::SimulationFailed(simulation)) => {
+            Err(ActorError::MsgTraceError(simulation.exit_code, simulation.trace))
+        }
         Err(err) => Err(ActorError::MpoolCallError(err)),
     }
 }
 
+pub async fn build_change_worker(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+    new_worker_id: Address,
+) -> Result<Message, ActorError> {
+    let params = ChangeWorkerParams { miner_id, new_worker_id };
+
+    match mpool_build(rpc, from, actor_id, 18, TokenAmount::from_atto(0), params).await {
+        Ok(msg) => Ok(msg),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn simulate_change_worker(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+    new_worker_id: Address,
+) -> Result<Simulation, ActorError> {
+    let params = ChangeWorkerParams { miner_id, new_worker_id };
+
+    match mpool_dry_run(rpc, from, actor_id, 18, TokenAmount::from_atto(0), params).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn propose_change_worker(
+    rpc: RpcEndpoint,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    actor_id: Address,
+    miner_id: Address,
+    new_worker_id: Address,
+) -> Result<ProposeReturn, ActorError> {
+    let params = ChangeWorkerParams { miner_id, new_worker_id };
+    let inner = InnerMessage::new(actor_id, TokenAmount::from_atto(0), 18, params)?;
+    match propose(rpc, signer, signer_key_info, msig, inner).await {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(ActorError::MsigCallError(err)),
+    }
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Default)]
 struct WithdrawMinerParams {
     miner_id: Address,
@@ -304,19 +404,102 @@ pub async fn withdraw_miner(
 ) -> Result<(), ActorError> {
     let params = WithdrawMinerParams { miner_id, amount };
 
-    match mpool_push::<_, CidJson>(rpc.clone(), from, from_key_info, actor_id, 19, TokenAmount::from_atto(0), params)
-        .await
-    {
-        Ok(res) => match wait_msg::<serde_json::Value>(rpc, res).await {
-            Ok(_) => Ok(()),
-            Err(StateError::ParseByYourSelf(s)) => {
-                warn!("> State cannot parse {}, parse by youself!", &s);
-                let s = base64::decode_config(&s, base64::STANDARD)?;
-                info!("> {}", String::from_utf8(s)?);
-                Ok(())
-            }
-            Err(err) => Err(ActorError::StateCallError(err)),
-        },
+    match mpool_push(rpc.clone(), from, from_key_info.clone(), actor_id, 19, TokenAmount::from_atto(0), params).await {
+        Ok(res) => wait_msg_traced(rpc, from_key_info, res).await,
+        Err(MpoolError::SimulationFailed(simulation)) => {
+            Err(ActorError::MsgTraceError(simulation.exit_code, simulation.trace))
+        }
         Err(err) => Err(ActorError::MpoolCallError(err)),
     }
 }
+
+pub async fn build_withdraw_miner(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+    amount: TokenAmount,
+) -> Result<Message, ActorError> {
+    let params = WithdrawMinerParams { miner_id, amount };
+
+    match mpool_build(rpc, from, actor_id, 19, TokenAmount::from_atto(0), params).await {
+        Ok(msg) => Ok(msg),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn simulate_withdraw_miner(
+    rpc: RpcEndpoint,
+    from: Address,
+    actor_id: Address,
+    miner_id: Address,
+    amount: TokenAmount,
+) -> Result<Simulation, ActorError> {
+    let params = WithdrawMinerParams { miner_id, amount };
+
+    match mpool_dry_run(rpc, from, actor_id, 19, TokenAmount::from_atto(0), params).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(ActorError::MpoolCallError(err)),
+    }
+}
+
+pub async fn propose_withdraw_miner(
+    rpc: RpcEndpoint,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    actor_id: Address,
+    miner_id: Address,
+    amount: TokenAmount,
+) -> Result<ProposeReturn, ActorError> {
+    let params = WithdrawMinerParams { miner_id, amount };
+    let inner = InnerMessage::new(actor_id, TokenAmount::from_atto(0), 19, params)?;
+    match propose(rpc, signer, signer_key_info, msig, inner).await {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(ActorError::MsigCallError(err)),
+    }
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, Default)]
+pub struct Deposit {
+    pub account: Address,
+    pub amount: TokenAmount,
+}
+
+// State root of the owner actor, fees are percents of the mining reward
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, Default)]
+pub struct OwnerActorState {
+    pub miners: Vec<Address>,
+    pub operator_fee: u64,
+    pub dataset_fee: u64,
+    pub device_holder_fee: u64,
+    pub deposits: Vec<Deposit>,
+}
+
+impl ReturnDecode for OwnerActorState {
+    // StateReadState cannot render a custom actor, the cbor head is the only source
+    fn decode_json(_ret: serde_json::Value) -> Result<Self, StateError> {
+        Err(StateError::ConvertReturnDecError("owner actor state is only decoded from cbor".to_string()))
+    }
+
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        decode_cbor::<OwnerActorState>(ret)
+    }
+}
+
+impl fmt::Display for OwnerActorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  Miners:            {:?}",
+            self.miners.iter().map(|miner| miner.to_string()).collect::<Vec<_>>()
+        )?;
+        writeln!(f, "  Operator Fee:      {}%", self.operator_fee)?;
+        writeln!(f, "  Dataset Fee:       {}%", self.dataset_fee)?;
+        write!(f, "  Device Holder Fee: {}%", self.device_holder_fee)?;
+        for deposit in &self.deposits {
+            write!(f, "\n  Deposit:           {} {}", deposit.account, deposit.amount)?;
+        }
+        Ok(())
+    }
+}
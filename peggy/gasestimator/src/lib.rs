@@ -1,8 +1,6 @@
-use forest_json::message::json::MessageJson;
-use forest_rpc_api::gas_api;
 use fvm_shared::{econ::TokenAmount, message::Message};
-use rpc::{RpcEndpoint, RpcError};
-use serde_json::json;
+use lotusapi::LotusApi;
+use rpc::RpcError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,23 +9,11 @@ pub enum GasEstimatorError {
     RpcRequestError(#[from] RpcError),
 }
 
-pub async fn estimate_msg_gas(rpc: RpcEndpoint, msg: Message) -> Result<Message, GasEstimatorError> {
-    let msg_json = MessageJson::from(msg.clone());
-
+pub async fn estimate_msg_gas<A: LotusApi>(api: A, msg: Message) -> Result<Message, GasEstimatorError> {
     let max_fee = TokenAmount::from_nano(1_000_000_000);
 
-    match rpc
-        .post::<_, MessageJson>(gas_api::GAS_ESTIMATE_MESSAGE_GAS, vec![
-            json!(msg_json),
-            json!({"MaxFee": max_fee.atto().to_string(),}),
-            json!([]),
-        ])
-        .await
-    {
-        Ok(res) => {
-            let MessageJson(res) = res;
-            Ok(res)
-        }
+    match api.gas_estimate_message_gas(msg, max_fee).await {
+        Ok(res) => Ok(res),
         Err(err) => Err(GasEstimatorError::RpcRequestError(err)),
     }
 }
@@ -0,0 +1,298 @@
+use cid::Cid;
+use fvm_shared::{address::Address, bigint::BigInt, econ::TokenAmount, error::ExitCode, message::Message};
+use log::info;
+use lotusapi::{LotusApi, ReceiptJson, LOOKBACK_NO_LIMIT};
+use rpc::RpcError;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use state::{
+    lookup_id,
+    robust_address,
+    AnyAddress,
+    CancellationToken,
+    ChainEvent,
+    ChainWatcher,
+    StateError,
+    TipsetRef,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+use thiserror::Error;
+
+// One day of epochs, history older than that is only indexed when the cursor is behind it
+pub const DEFAULT_LOOKBACK: i64 = 2880;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS watched (
+    id TEXT PRIMARY KEY,
+    robust TEXT
+);
+CREATE TABLE IF NOT EXISTS messages (
+    cid TEXT PRIMARY KEY,
+    height INTEGER NOT NULL,
+    tipset TEXT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_id TEXT NOT NULL,
+    method INTEGER NOT NULL,
+    value TEXT NOT NULL,
+    exit_code INTEGER NOT NULL,
+    gas_used INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS messages_to_height ON messages (to_id, height);
+CREATE INDEX IF NOT EXISTS messages_tipset ON messages (tipset);
+CREATE TABLE IF NOT EXISTS cursor (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    height INTEGER NOT NULL
+);
+";
+
+#[derive(Error, Debug)]
+pub enum IndexerError {
+    #[error("sqlite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("rpc error: {0}")]
+    RpcError(#[from] RpcError),
+    #[error("state call error: {0}")]
+    StateCallError(#[from] StateError),
+    #[error("parse indexed row error: {0}")]
+    ParseRowError(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexedMessage {
+    pub cid: Cid,
+    pub height: i64,
+    pub from: Address,
+    pub to: Address,
+    pub method: u64,
+    pub value: TokenAmount,
+    pub exit_code: ExitCode,
+    pub gas_used: i64,
+}
+
+impl IndexedMessage {
+    fn from_row(row: &Row) -> Result<Self, IndexerError> {
+        let parse = |err: String| IndexerError::ParseRowError(err);
+        let value = BigInt::from_str(&row.get::<_, String>(5)?).map_err(|err| parse(err.to_string()))?;
+        Ok(Self {
+            cid: Cid::from_str(&row.get::<_, String>(0)?).map_err(|err| parse(err.to_string()))?,
+            height: row.get(1)?,
+            from: Address::from_str(&row.get::<_, String>(2)?).map_err(|err| parse(err.to_string()))?,
+            to: Address::from_str(&row.get::<_, String>(3)?).map_err(|err| parse(err.to_string()))?,
+            method: row.get(4)?,
+            value: TokenAmount::from_atto(value),
+            exit_code: ExitCode::new(row.get(6)?),
+            gas_used: row.get(7)?,
+        })
+    }
+}
+
+fn tipset_key(key: &[Cid]) -> String {
+    key.iter().map(|cid| cid.to_string()).collect::<Vec<String>>().join(",")
+}
+
+fn load_watched(db: &Connection) -> Result<HashMap<Address, Address>, IndexerError> {
+    let mut watched = HashMap::new();
+    let mut stmt = db.prepare("SELECT id, robust FROM watched")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id =
+            Address::from_str(&row.get::<_, String>(0)?).map_err(|err| IndexerError::ParseRowError(err.to_string()))?;
+        watched.insert(id, id);
+        if let Some(robust) = row.get::<_, Option<String>>(1)?.and_then(|robust| Address::from_str(&robust).ok()) {
+            watched.insert(robust, id);
+        }
+    }
+    Ok(watched)
+}
+
+pub struct Indexer<A> {
+    api: A,
+    db: Connection,
+    // Messages may address a watched actor by its id or robust address, both map to the id
+    watched: HashMap<Address, Address>,
+    // Epochs below the head backfilled on the first run, when there is no cursor yet
+    lookback: i64,
+}
+
+impl<A: LotusApi> Indexer<A> {
+    pub fn open(api: A, path: impl AsRef<Path>) -> Result<Self, IndexerError> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+
+        let watched = load_watched(&db)?;
+
+        Ok(Self { api, db, watched, lookback: DEFAULT_LOOKBACK })
+    }
+
+    pub fn lookback(mut self, epochs: i64) -> Self {
+        self.lookback = epochs.max(0);
+        self
+    }
+
+    pub async fn watch(&mut self, addr: impl Into<AnyAddress>) -> Result<Address, IndexerError> {
+        let id = lookup_id(self.api.clone(), addr).await?;
+        let robust = match robust_address(self.api.clone(), id).await {
+            Ok(AnyAddress::Filecoin(robust)) => Some(robust),
+            _ => None,
+        };
+
+        self.db.execute("INSERT OR REPLACE INTO watched (id, robust) VALUES (?1, ?2)", params![
+            id.to_string(),
+            robust.map(|robust| robust.to_string())
+        ])?;
+        self.watched.insert(id, id);
+        if let Some(robust) = robust {
+            self.watched.insert(robust, id);
+        }
+
+        Ok(id)
+    }
+
+    pub fn cursor(&self) -> Result<Option<i64>, IndexerError> {
+        Ok(self.db.query_row("SELECT height FROM cursor WHERE id = 0", [], |row| row.get(0)).optional()?)
+    }
+
+    fn set_cursor(&self, height: i64) -> Result<(), IndexerError> {
+        self.db.execute("INSERT OR REPLACE INTO cursor (id, height) VALUES (0, ?1)", params![height])?;
+        Ok(())
+    }
+
+    fn insert(
+        &self,
+        cid: Cid,
+        msg: &Message,
+        receipt: &ReceiptJson,
+        height: i64,
+        tipset: &str,
+    ) -> Result<bool, IndexerError> {
+        let to = match self.watched.get(&msg.to) {
+            Some(to) => *to,
+            None => return Ok(false),
+        };
+        self.db.execute(
+            "INSERT OR REPLACE INTO messages (cid, height, tipset, from_address, to_id, method, value, exit_code, gas_used)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                cid.to_string(),
+                height,
+                tipset,
+                msg.from.to_string(),
+                to.to_string(),
+                msg.method_num,
+                msg.value.atto().to_string(),
+                receipt.exit_code.value(),
+                receipt.gas_used,
+            ],
+        )?;
+        Ok(true)
+    }
+
+    // StateListMessages walks the chain back from the head, it's slow but only runs once per cursor gap.
+    // It matches the To address as sent, so both the id and the robust address of each actor are listed
+    pub async fn backfill(&mut self, from_height: i64) -> Result<usize, IndexerError> {
+        let mut indexed = 0;
+        let mut seen = HashSet::new();
+        let addrs = self.watched.keys().cloned().collect::<Vec<Address>>();
+        for addr in addrs {
+            for cid in self.api.state_list_messages(addr, from_height).await? {
+                if !seen.insert(cid) {
+                    continue;
+                }
+                let msg = self.api.chain_get_message(cid).await?;
+                let lookup = match self.api.state_search_msg(cid, LOOKBACK_NO_LIMIT, true).await? {
+                    Some(lookup) => lookup,
+                    None => continue,
+                };
+                let tipset = tipset_key(lookup.tipset.0.cids());
+                if self.insert(cid, &msg, &lookup.receipt, lookup.height, &tipset)? {
+                    indexed += 1;
+                }
+            }
+        }
+        Ok(indexed)
+    }
+
+    // Receipts of a tipset's parent messages are in its blocks, so messages are indexed at the executing tipset
+    pub async fn index_tipset(&mut self, tipset: &TipsetRef) -> Result<usize, IndexerError> {
+        let block = match tipset.key.first() {
+            Some(block) => *block,
+            None => return Ok(0),
+        };
+        let msgs = self.api.chain_get_parent_messages(block).await?;
+        let receipts = self.api.chain_get_parent_receipts(block).await?;
+
+        let key = tipset_key(&tipset.key);
+        let mut indexed = 0;
+        for (msg, receipt) in msgs.iter().zip(receipts.iter()) {
+            if self.insert(msg.cid.0, &msg.message.0, receipt, tipset.height, &key)? {
+                indexed += 1;
+            }
+        }
+        Ok(indexed)
+    }
+
+    pub fn revert_tipset(&mut self, tipset: &TipsetRef) -> Result<usize, IndexerError> {
+        let reverted = self.db.execute("DELETE FROM messages WHERE tipset = ?1", params![tipset_key(&tipset.key)])?;
+        // A restart before the fork is applied must index the new tipsets at these heights
+        self.set_cursor(tipset.height - 1)?;
+        Ok(reverted)
+    }
+
+    // Backfills from the cursor, or lookback epochs below the head on the first run, once the watcher reports the
+    // head, then follows applies and reverts
+    pub async fn run(&mut self, cancel: CancellationToken) -> Result<(), IndexerError> {
+        let mut watcher = ChainWatcher::new(self.api.clone());
+        let mut backfilled = false;
+
+        loop {
+            let event = tokio::select! {
+                event = watcher.next() => event?,
+                _ = cancel.cancelled() => return Ok(()),
+            };
+
+            match event {
+                ChainEvent::Apply(tipset) => {
+                    if !backfilled {
+                        let from_height = match self.cursor()? {
+                            Some(cursor) => cursor,
+                            None => (tipset.height - self.lookback).max(0),
+                        };
+                        let indexed = self.backfill(from_height).await?;
+                        info!("> Backfilled {} messages since {}", indexed, from_height);
+                        backfilled = true;
+                    }
+                    let indexed = self.index_tipset(&tipset).await?;
+                    if indexed > 0 {
+                        info!("> Indexed {} messages at {}", indexed, tipset.height);
+                    }
+                    self.set_cursor(tipset.height)?;
+                }
+                ChainEvent::Revert(tipset) => {
+                    let reverted = self.revert_tipset(&tipset)?;
+                    if reverted > 0 {
+                        info!("> Reverted {} messages at {}", reverted, tipset.height);
+                    }
+                }
+                ChainEvent::Finalized(_) => {}
+            }
+        }
+    }
+
+    pub fn messages(&self, to: Address, limit: usize) -> Result<Vec<IndexedMessage>, IndexerError> {
+        let to = self.watched.get(&to).cloned().unwrap_or(to);
+        let mut stmt = self.db.prepare(
+            "SELECT cid, height, from_address, to_id, method, value, exit_code, gas_used
+             FROM messages WHERE to_id = ?1 ORDER BY height DESC LIMIT ?2",
+        )?;
+        let mut rows = stmt.query(params![to.to_string(), limit as i64])?;
+
+        let mut msgs = Vec::new();
+        while let Some(row) = rows.next()? {
+            msgs.push(IndexedMessage::from_row(row)?);
+        }
+        Ok(msgs)
+    }
+}
@@ -0,0 +1,107 @@
+// Lotus renders addresses, token amounts and big ints as strings, these adapt them for serde(with)
+pub mod address {
+    use fvm_shared::address::Address;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(addr: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&addr.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+        let addr = String::deserialize(deserializer)?;
+        Address::from_str(&addr).map_err(de::Error::custom)
+    }
+}
+
+pub mod opt_address {
+    use fvm_shared::address::Address;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    // Lotus renders an undefined address as <empty>
+    const EMPTY_ADDRESS: &str = "<empty>";
+
+    pub fn serialize<S: Serializer>(addr: &Option<Address>, serializer: S) -> Result<S::Ok, S::Error> {
+        match addr {
+            Some(addr) => serializer.serialize_str(&addr.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Address>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(addr) if addr != EMPTY_ADDRESS => Ok(Some(Address::from_str(&addr).map_err(de::Error::custom)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+pub mod vec_address {
+    use fvm_shared::address::Address;
+    use serde::{de, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(addrs: &[Address], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(addrs.len()))?;
+        for addr in addrs {
+            seq.serialize_element(&addr.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Address>, D::Error> {
+        Option::<Vec<String>>::deserialize(deserializer)?
+            .unwrap_or_default()
+            .iter()
+            .map(|addr| Address::from_str(addr).map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+// Traces of FEVM calls carry f4 addresses fvm_shared 2 cannot parse
+pub mod any_address {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    use crate::AnyAddress;
+
+    pub fn serialize<S: Serializer>(addr: &AnyAddress, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&addr.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AnyAddress, D::Error> {
+        let addr = String::deserialize(deserializer)?;
+        AnyAddress::from_str(&addr).map_err(de::Error::custom)
+    }
+}
+
+pub mod token_amount {
+    use fvm_shared::{bigint::BigInt, econ::TokenAmount};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(amount: &TokenAmount, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.atto().to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TokenAmount, D::Error> {
+        let amount = String::deserialize(deserializer)?;
+        Ok(TokenAmount::from_atto(BigInt::from_str(&amount).map_err(de::Error::custom)?))
+    }
+}
+
+pub mod bigint {
+    use fvm_shared::bigint::BigInt;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(int: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&int.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let int = String::deserialize(deserializer)?;
+        BigInt::from_str(&int).map_err(de::Error::custom)
+    }
+}
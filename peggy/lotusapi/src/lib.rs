@@ -0,0 +1,524 @@
+use async_trait::async_trait;
+use cid::Cid;
+use forest_blocks::tipset_keys_json::TipsetKeysJson;
+use forest_ipld::json::IpldJson;
+use forest_json::{cid::CidJson, message::json::MessageJson, signed_message::json::SignedMessageJson};
+use forest_message::signed_message::SignedMessage;
+use fvm_shared::{
+    address::{Address, Network, Protocol},
+    bigint::BigInt,
+    econ::TokenAmount,
+    error::ExitCode,
+    message::Message,
+};
+use log::warn;
+use rpc::{Batch, NodeInfo, RpcEndpoint, RpcError, Subscription, VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{fmt, str::FromStr};
+
+pub mod json;
+mod miner;
+mod msig;
+mod resolver;
+mod trace;
+
+pub use miner::{
+    bitfield_to_sectors,
+    sectors_to_bitfield,
+    BeneficiaryTerm,
+    Claim,
+    DeadlineInfo,
+    MinerInfo,
+    MinerPower,
+    MinerSectors,
+};
+pub use msig::MsigTransaction;
+pub use resolver::{AddressResolver, AnyAddress, EAM_NAMESPACE};
+pub use trace::{ExecutionTrace, GasCharge, InvocResult, TraceMessage, TraceReceipt};
+
+pub const MPOOL_GET_NONCE: &str = "Filecoin.MpoolGetNonce";
+pub const MPOOL_PUSH: &str = "Filecoin.MpoolPush";
+pub const GAS_ESTIMATE_MESSAGE_GAS: &str = "Filecoin.GasEstimateMessageGas";
+pub const WALLET_BALANCE: &str = "Filecoin.WalletBalance";
+pub const STATE_WAIT_MSG: &str = "Filecoin.StateWaitMsg";
+pub const STATE_WAIT_MSG_LIMITED: &str = "Filecoin.StateWaitMsgLimited";
+pub const STATE_SEARCH_MSG: &str = "Filecoin.StateSearchMsg";
+pub const STATE_SEARCH_MSG_LIMITED: &str = "Filecoin.StateSearchMsgLimited";
+pub const CHAIN_HEAD: &str = "Filecoin.ChainHead";
+pub const CHAIN_NOTIFY: &str = "Filecoin.ChainNotify";
+pub const CHAIN_GET_TIPSET: &str = "Filecoin.ChainGetTipSet";
+pub const CHAIN_GET_TIPSET_BY_HEIGHT: &str = "Filecoin.ChainGetTipSetByHeight";
+pub const CHAIN_GET_MESSAGE: &str = "Filecoin.ChainGetMessage";
+pub const CHAIN_GET_PARENT_MESSAGES: &str = "Filecoin.ChainGetParentMessages";
+pub const CHAIN_GET_PARENT_RECEIPTS: &str = "Filecoin.ChainGetParentReceipts";
+pub const STATE_LIST_MESSAGES: &str = "Filecoin.StateListMessages";
+pub const STATE_LOOKUP_ID: &str = "Filecoin.StateLookupID";
+pub const STATE_ACCOUNT_KEY: &str = "Filecoin.StateAccountKey";
+
+pub const STATE_MINER_INFO: &str = "Filecoin.StateMinerInfo";
+pub const MSIG_GET_PENDING: &str = "Filecoin.MsigGetPending";
+pub const STATE_MINER_POWER: &str = "Filecoin.StateMinerPower";
+pub const STATE_MINER_AVAILABLE_BALANCE: &str = "Filecoin.StateMinerAvailableBalance";
+pub const STATE_MINER_FAULTS: &str = "Filecoin.StateMinerFaults";
+pub const STATE_MINER_PROVING_DEADLINE: &str = "Filecoin.StateMinerProvingDeadline";
+pub const STATE_MINER_SECTOR_COUNT: &str = "Filecoin.StateMinerSectorCount";
+
+pub const STATE_GET_ACTOR: &str = "Filecoin.StateGetActor";
+pub const STATE_READ_STATE: &str = "Filecoin.StateReadState";
+pub const CHAIN_READ_OBJ: &str = "Filecoin.ChainReadObj";
+pub const STATE_REPLAY: &str = "Filecoin.StateReplay";
+pub const STATE_CALL: &str = "Filecoin.StateCall";
+
+pub const LOOKBACK_NO_LIMIT: i64 = -1;
+// Epochs after which a tipset cannot be reverted anymore
+pub const FINALITY: i64 = 900;
+
+pub const MAINNET: &str = "mainnet";
+
+// Forest registers StateLookupID with a different casing than Lotus
+const FOREST_STATE_LOOKUP_ID: &str = "Filecoin.StateLookupId";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReceiptJson {
+    pub exit_code: ExitCode,
+    #[serde(rename = "Return")]
+    pub return_data: Option<String>,
+    pub gas_used: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageLookup {
+    pub receipt: ReceiptJson,
+    #[serde(rename = "TipSet")]
+    pub tipset: TipsetKeysJson,
+    pub height: i64,
+    pub message: CidJson,
+    pub return_dec: IpldJson,
+}
+
+impl fmt::Debug for MessageLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Receipt {}", serde_json::to_string(&self.receipt).unwrap())?;
+        write!(f, "Tipset {:?}", self.tipset)?;
+        write!(f, "Height {}", self.height)?;
+        write!(f, "Message {:?}", self.message)?;
+        write!(f, "ReturnDec {:?}", serde_json::to_string(&self.return_dec).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActorJson {
+    pub code: CidJson,
+    pub head: CidJson,
+    pub nonce: u64,
+    #[serde(with = "json::token_amount")]
+    pub balance: TokenAmount,
+    // Robust address of the actor, only reported by nodes supporting delegated addresses
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActorStateJson {
+    #[serde(with = "json::token_amount")]
+    pub balance: TokenAmount,
+    pub code: CidJson,
+    pub state: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ParentMessage {
+    pub cid: CidJson,
+    pub message: MessageJson,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BlockHeaderJson {
+    pub parents: Vec<CidJson>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct TipsetJson {
+    pub cids: Vec<CidJson>,
+    pub blocks: Vec<BlockHeaderJson>,
+    pub height: i64,
+}
+
+impl TipsetJson {
+    pub fn key(&self) -> Vec<Cid> {
+        self.cids.iter().map(|cid| cid.0).collect()
+    }
+
+    // All blocks of a tipset share the same parents
+    pub fn parents(&self) -> Vec<Cid> {
+        match self.blocks.first() {
+            Some(block) => block.parents.iter().map(|cid| cid.0).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn parse_address(addr: String) -> Result<Address, RpcError> {
+    Address::from_str(&addr).map_err(|_| RpcError::RpcResponseParseError)
+}
+
+fn parse_token_amount(amount: String) -> Result<TokenAmount, RpcError> {
+    match BigInt::from_str(&amount) {
+        Ok(amount) => Ok(TokenAmount::from_atto(amount)),
+        Err(_) => Err(RpcError::RpcResponseParseError),
+    }
+}
+
+async fn negotiate(rpc: &RpcEndpoint) -> NodeInfo {
+    match rpc.node_info().await {
+        Ok(info) => info,
+        Err(err) => {
+            warn!("Fail to negotiate api version with {}: {}, assume lotus v0", rpc.url(), err);
+            NodeInfo::default()
+        }
+    }
+}
+
+// Every network but mainnet renders addresses with the testnet prefix
+pub fn network_prefix(network: &str) -> Network {
+    match network {
+        MAINNET => Network::Mainnet,
+        _ => Network::Testnet,
+    }
+}
+
+// Id addresses built in code, like the builtin actors, carry the default prefix on every network
+pub fn is_address_of_network(addr: &Address, network: &str) -> bool {
+    addr.protocol() == Protocol::ID || addr.network() == network_prefix(network)
+}
+
+// Generated keys render with the default mainnet prefix until they are tagged with the network they are used on
+pub fn with_network_prefix(mut addr: Address, network: &str) -> Address {
+    addr.set_network(network_prefix(network));
+    addr
+}
+
+#[async_trait]
+pub trait LotusApi: Clone + Send + Sync {
+    // Network the caller signs for, not a node call
+    fn expected_network(&self) -> Option<String>;
+
+    async fn version(&self) -> Result<NodeInfo, RpcError>;
+
+    async fn mpool_get_nonce(&self, addr: Address) -> Result<u64, RpcError>;
+
+    async fn mpool_push(&self, smsg: SignedMessage) -> Result<Cid, RpcError>;
+
+    async fn gas_estimate_message_gas(&self, msg: Message, max_fee: TokenAmount) -> Result<Message, RpcError>;
+
+    async fn wallet_balance(&self, addr: Address) -> Result<TokenAmount, RpcError>;
+
+    async fn wallet_balances(&self, addrs: Vec<Address>) -> Result<Vec<Result<TokenAmount, RpcError>>, RpcError> {
+        let mut balances = Vec::new();
+        for addr in addrs {
+            balances.push(self.wallet_balance(addr).await);
+        }
+        Ok(balances)
+    }
+
+    async fn chain_head_height(&self) -> Result<i64, RpcError>;
+
+    async fn chain_head(&self) -> Result<TipsetJson, RpcError>;
+
+    async fn chain_get_tipset(&self, key: Vec<Cid>) -> Result<TipsetJson, RpcError>;
+
+    async fn chain_get_tipset_by_height(&self, height: i64) -> Result<TipsetJson, RpcError>;
+
+    // Head changes pushed by the node, only websocket endpoints can serve them
+    async fn chain_notify(&self) -> Result<Subscription<serde_json::Value>, RpcError> {
+        Err(RpcError::SubscriptionUnsupported)
+    }
+
+    async fn chain_get_message(&self, cid: Cid) -> Result<Message, RpcError>;
+
+    // Messages of the parent tipset and their receipts, in execution order
+    async fn chain_get_parent_messages(&self, block: Cid) -> Result<Vec<ParentMessage>, RpcError>;
+
+    async fn chain_get_parent_receipts(&self, block: Cid) -> Result<Vec<ReceiptJson>, RpcError>;
+
+    // Messages sent to the address and executed since from_height up to the head
+    async fn state_list_messages(&self, to: Address, from_height: i64) -> Result<Vec<Cid>, RpcError>;
+
+    async fn state_wait_msg(
+        &self,
+        cid: Cid,
+        confidence: i64,
+        lookback: i64,
+        allow_replaced: bool,
+    ) -> Result<MessageLookup, RpcError>;
+
+    async fn state_search_msg(
+        &self,
+        cid: Cid,
+        lookback: i64,
+        allow_replaced: bool,
+    ) -> Result<Option<MessageLookup>, RpcError>;
+
+    async fn state_network_name(&self) -> Result<String, RpcError>;
+
+    // An empty tipset key looks the address up at the head
+    async fn state_lookup_id(&self, addr: &AnyAddress, tipset: Vec<Cid>) -> Result<Address, RpcError>;
+
+    async fn state_account_key(&self, addr: Address) -> Result<Address, RpcError>;
+
+    async fn state_get_actor(&self, addr: Address) -> Result<ActorJson, RpcError>;
+
+    async fn state_read_state(&self, addr: Address) -> Result<ActorStateJson, RpcError>;
+
+    async fn chain_read_obj(&self, cid: Cid) -> Result<Vec<u8>, RpcError>;
+
+    async fn state_replay(&self, cid: Cid) -> Result<InvocResult, RpcError>;
+
+    // Applies the message on top of the head without a signature, nothing is persisted
+    async fn state_call(&self, msg: Message) -> Result<InvocResult, RpcError>;
+
+    async fn state_miner_info(&self, miner: Address) -> Result<MinerInfo, RpcError>;
+
+    async fn msig_get_pending(&self, msig: Address) -> Result<Vec<MsigTransaction>, RpcError>;
+
+    async fn state_miner_power(&self, miner: Address) -> Result<MinerPower, RpcError>;
+
+    async fn state_miner_available_balance(&self, miner: Address) -> Result<TokenAmount, RpcError>;
+
+    async fn state_miner_faults(&self, miner: Address) -> Result<Vec<u64>, RpcError>;
+
+    async fn state_miner_proving_deadline(&self, miner: Address) -> Result<DeadlineInfo, RpcError>;
+
+    async fn state_miner_sector_count(&self, miner: Address) -> Result<MinerSectors, RpcError>;
+}
+
+#[async_trait]
+impl LotusApi for RpcEndpoint {
+    fn expected_network(&self) -> Option<String> {
+        RpcEndpoint::expected_network(self)
+    }
+
+    async fn version(&self) -> Result<NodeInfo, RpcError> {
+        self.node_info().await
+    }
+
+    async fn mpool_get_nonce(&self, addr: Address) -> Result<u64, RpcError> {
+        self.post::<_, u64>(MPOOL_GET_NONCE, vec![addr.to_string()]).await
+    }
+
+    async fn mpool_push(&self, smsg: SignedMessage) -> Result<Cid, RpcError> {
+        let CidJson(cid) = self.post::<_, CidJson>(MPOOL_PUSH, vec![SignedMessageJson(smsg)]).await?;
+        Ok(cid)
+    }
+
+    async fn gas_estimate_message_gas(&self, msg: Message, max_fee: TokenAmount) -> Result<Message, RpcError> {
+        let MessageJson(msg) = self
+            .post::<_, MessageJson>(GAS_ESTIMATE_MESSAGE_GAS, vec![
+                json!(MessageJson(msg)),
+                json!({"MaxFee": max_fee.atto().to_string(),}),
+                json!([]),
+            ])
+            .await?;
+        Ok(msg)
+    }
+
+    async fn wallet_balance(&self, addr: Address) -> Result<TokenAmount, RpcError> {
+        let balance = self.post::<_, String>(WALLET_BALANCE, vec![addr.to_string()]).await?;
+        parse_token_amount(balance)
+    }
+
+    async fn wallet_balances(&self, addrs: Vec<Address>) -> Result<Vec<Result<TokenAmount, RpcError>>, RpcError> {
+        let mut batch = Batch::new();
+        let calls = addrs
+            .iter()
+            .map(|addr| batch.call::<_, String>(WALLET_BALANCE, vec![addr.to_string()]))
+            .collect::<Vec<_>>();
+
+        let mut results = self.post_batch(batch).await?;
+
+        Ok(calls.into_iter().map(|call| results.take(call).and_then(parse_token_amount)).collect())
+    }
+
+    async fn chain_head_height(&self) -> Result<i64, RpcError> {
+        let head = self.chain_head().await?;
+        Ok(head.height)
+    }
+
+    async fn chain_head(&self) -> Result<TipsetJson, RpcError> {
+        self.post::<_, TipsetJson>(CHAIN_HEAD, json!([])).await
+    }
+
+    async fn chain_get_tipset(&self, key: Vec<Cid>) -> Result<TipsetJson, RpcError> {
+        let key = key.into_iter().map(CidJson).collect::<Vec<CidJson>>();
+        self.post::<_, TipsetJson>(CHAIN_GET_TIPSET, json!([key])).await
+    }
+
+    async fn chain_get_tipset_by_height(&self, height: i64) -> Result<TipsetJson, RpcError> {
+        self.post::<_, TipsetJson>(CHAIN_GET_TIPSET_BY_HEIGHT, json!([height, []])).await
+    }
+
+    async fn chain_notify(&self) -> Result<Subscription<serde_json::Value>, RpcError> {
+        match self.is_websocket() {
+            true => self.subscribe::<_, serde_json::Value>(CHAIN_NOTIFY, json!([])).await,
+            false => Err(RpcError::SubscriptionUnsupported),
+        }
+    }
+
+    async fn chain_get_message(&self, cid: Cid) -> Result<Message, RpcError> {
+        let MessageJson(msg) = self.post::<_, MessageJson>(CHAIN_GET_MESSAGE, json!([CidJson(cid)])).await?;
+        Ok(msg)
+    }
+
+    async fn chain_get_parent_messages(&self, block: Cid) -> Result<Vec<ParentMessage>, RpcError> {
+        let msgs =
+            self.post::<_, Option<Vec<ParentMessage>>>(CHAIN_GET_PARENT_MESSAGES, json!([CidJson(block)])).await?;
+        Ok(msgs.unwrap_or_default())
+    }
+
+    async fn chain_get_parent_receipts(&self, block: Cid) -> Result<Vec<ReceiptJson>, RpcError> {
+        let receipts =
+            self.post::<_, Option<Vec<ReceiptJson>>>(CHAIN_GET_PARENT_RECEIPTS, json!([CidJson(block)])).await?;
+        Ok(receipts.unwrap_or_default())
+    }
+
+    async fn state_list_messages(&self, to: Address, from_height: i64) -> Result<Vec<Cid>, RpcError> {
+        let filter = json!({"To": to.to_string()});
+        let cids = self.post::<_, Option<Vec<CidJson>>>(STATE_LIST_MESSAGES, json!([filter, [], from_height])).await?;
+        Ok(cids.unwrap_or_default().into_iter().map(|cid| cid.0).collect())
+    }
+
+    // Lotus v0 and Forest always allow replaced messages, v0 only limits lookback with the *Limited variants
+    async fn state_wait_msg(
+        &self,
+        cid: Cid,
+        confidence: i64,
+        lookback: i64,
+        allow_replaced: bool,
+    ) -> Result<MessageLookup, RpcError> {
+        let info = negotiate(self).await;
+        let (method, params) = if info.is_api_v1() {
+            (STATE_WAIT_MSG, json!([CidJson(cid), confidence, lookback, allow_replaced]))
+        } else if info.is_forest() || lookback == LOOKBACK_NO_LIMIT {
+            (STATE_WAIT_MSG, json!([CidJson(cid), confidence]))
+        } else {
+            (STATE_WAIT_MSG_LIMITED, json!([CidJson(cid), confidence, lookback]))
+        };
+        self.post::<_, MessageLookup>(method, params).await
+    }
+
+    async fn state_search_msg(
+        &self,
+        cid: Cid,
+        lookback: i64,
+        allow_replaced: bool,
+    ) -> Result<Option<MessageLookup>, RpcError> {
+        let info = negotiate(self).await;
+        let (method, params) = if info.is_api_v1() {
+            (STATE_SEARCH_MSG, json!([[], CidJson(cid), lookback, allow_replaced]))
+        } else if info.is_forest() || lookback == LOOKBACK_NO_LIMIT {
+            (STATE_SEARCH_MSG, json!([CidJson(cid)]))
+        } else {
+            (STATE_SEARCH_MSG_LIMITED, json!([CidJson(cid), lookback]))
+        };
+        self.post::<_, Option<MessageLookup>>(method, params).await
+    }
+
+    async fn state_network_name(&self) -> Result<String, RpcError> {
+        Ok(self.node_info().await?.network)
+    }
+
+    async fn state_lookup_id(&self, addr: &AnyAddress, tipset: Vec<Cid>) -> Result<Address, RpcError> {
+        let method = match negotiate(self).await.is_forest() {
+            true => FOREST_STATE_LOOKUP_ID,
+            false => STATE_LOOKUP_ID,
+        };
+        let tipset = tipset.into_iter().map(CidJson).collect::<Vec<CidJson>>();
+        let addr = self.post::<_, String>(method, json!([addr.to_string(), tipset])).await?;
+        parse_address(addr)
+    }
+
+    async fn state_account_key(&self, addr: Address) -> Result<Address, RpcError> {
+        let addr = self.post::<_, String>(STATE_ACCOUNT_KEY, json!([addr.to_string(), []])).await?;
+        parse_address(addr)
+    }
+
+    async fn state_get_actor(&self, addr: Address) -> Result<ActorJson, RpcError> {
+        self.post::<_, ActorJson>(STATE_GET_ACTOR, json!([addr.to_string(), []])).await
+    }
+
+    async fn state_read_state(&self, addr: Address) -> Result<ActorStateJson, RpcError> {
+        self.post::<_, ActorStateJson>(STATE_READ_STATE, json!([addr.to_string(), []])).await
+    }
+
+    async fn chain_read_obj(&self, cid: Cid) -> Result<Vec<u8>, RpcError> {
+        let obj = self.post::<_, String>(CHAIN_READ_OBJ, json!([CidJson(cid)])).await?;
+        base64::decode_config(obj, base64::STANDARD).map_err(|_| RpcError::RpcResponseParseError)
+    }
+
+    // An empty tipset key lets the node find the tipset the message was executed in
+    async fn state_replay(&self, cid: Cid) -> Result<InvocResult, RpcError> {
+        self.post::<_, InvocResult>(STATE_REPLAY, json!([[], CidJson(cid)])).await
+    }
+
+    async fn state_call(&self, msg: Message) -> Result<InvocResult, RpcError> {
+        self.post::<_, InvocResult>(STATE_CALL, json!([MessageJson(msg), []])).await
+    }
+
+    async fn state_miner_info(&self, miner: Address) -> Result<MinerInfo, RpcError> {
+        self.post::<_, MinerInfo>(STATE_MINER_INFO, json!([miner.to_string(), []])).await
+    }
+
+    async fn msig_get_pending(&self, msig: Address) -> Result<Vec<MsigTransaction>, RpcError> {
+        let txns =
+            self.post::<_, Option<Vec<MsigTransaction>>>(MSIG_GET_PENDING, json!([msig.to_string(), []])).await?;
+        Ok(txns.unwrap_or_default())
+    }
+
+    async fn state_miner_power(&self, miner: Address) -> Result<MinerPower, RpcError> {
+        self.post::<_, MinerPower>(STATE_MINER_POWER, json!([miner.to_string(), []])).await
+    }
+
+    async fn state_miner_available_balance(&self, miner: Address) -> Result<TokenAmount, RpcError> {
+        let balance = self.post::<_, String>(STATE_MINER_AVAILABLE_BALANCE, json!([miner.to_string(), []])).await?;
+        parse_token_amount(balance)
+    }
+
+    async fn state_miner_faults(&self, miner: Address) -> Result<Vec<u64>, RpcError> {
+        let faults = self.post::<_, Vec<u64>>(STATE_MINER_FAULTS, json!([miner.to_string(), []])).await?;
+        Ok(bitfield_to_sectors(&faults))
+    }
+
+    async fn state_miner_proving_deadline(&self, miner: Address) -> Result<DeadlineInfo, RpcError> {
+        self.post::<_, DeadlineInfo>(STATE_MINER_PROVING_DEADLINE, json!([miner.to_string(), []])).await
+    }
+
+    async fn state_miner_sector_count(&self, miner: Address) -> Result<MinerSectors, RpcError> {
+        self.post::<_, MinerSectors>(STATE_MINER_SECTOR_COUNT, json!([miner.to_string(), []])).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_prefix_matches_network() {
+        let actor = Address::new_actor(b"peggy");
+        let testnet = with_network_prefix(actor, "calibrationnet");
+
+        assert!(is_address_of_network(&actor, MAINNET));
+        assert!(!is_address_of_network(&actor, "calibrationnet"));
+        assert!(is_address_of_network(&testnet, "calibrationnet"));
+        assert!(!is_address_of_network(&testnet, MAINNET));
+        // Builtin actors are addressed by id on every network
+        assert!(is_address_of_network(&Address::new_id(4), "calibrationnet"));
+    }
+}
@@ -0,0 +1,120 @@
+use fvm_shared::{address::Address, bigint::BigInt, econ::TokenAmount};
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct BeneficiaryTerm {
+    #[serde(with = "json::token_amount")]
+    pub quota: TokenAmount,
+    #[serde(with = "json::token_amount")]
+    pub used_quota: TokenAmount,
+    pub expiration: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerInfo {
+    #[serde(with = "json::address")]
+    pub owner: Address,
+    #[serde(with = "json::address")]
+    pub worker: Address,
+    #[serde(with = "json::opt_address", default)]
+    pub new_worker: Option<Address>,
+    #[serde(with = "json::vec_address", default)]
+    pub control_addresses: Vec<Address>,
+    pub worker_change_epoch: i64,
+    #[serde(rename = "PeerId")]
+    pub peer_id: Option<String>,
+    #[serde(rename = "WindowPoStProofType")]
+    pub window_post_proof_type: i64,
+    pub sector_size: u64,
+    #[serde(rename = "WindowPoStPartitionSectors")]
+    pub window_post_partition_sectors: u64,
+    pub consensus_fault_elapsed: i64,
+    // Beneficiary exists since network version 17
+    #[serde(with = "json::opt_address", default)]
+    pub beneficiary: Option<Address>,
+    #[serde(default)]
+    pub beneficiary_term: Option<BeneficiaryTerm>,
+    #[serde(with = "json::opt_address", default)]
+    pub pending_owner_address: Option<Address>,
+}
+
+impl MinerInfo {
+    pub fn is_controlled_by(&self, addr: &Address) -> bool {
+        self.owner == *addr || self.worker == *addr || self.control_addresses.contains(addr)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct Claim {
+    #[serde(with = "json::bigint")]
+    pub raw_byte_power: BigInt,
+    #[serde(with = "json::bigint")]
+    pub quality_adj_power: BigInt,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerPower {
+    pub miner_power: Claim,
+    pub total_power: Claim,
+    pub has_min_power: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeadlineInfo {
+    pub current_epoch: i64,
+    pub period_start: i64,
+    pub index: u64,
+    pub open: i64,
+    pub close: i64,
+    pub challenge: i64,
+    pub fault_cutoff: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerSectors {
+    pub live: u64,
+    pub active: u64,
+    pub faulty: u64,
+}
+
+// Lotus renders bitfields as RLE+ run lengths, alternating unset and set runs
+pub fn bitfield_to_sectors(runs: &[u64]) -> Vec<u64> {
+    let mut sectors = Vec::new();
+    let mut position = 0;
+    for (i, run) in runs.iter().enumerate() {
+        if i % 2 == 1 {
+            sectors.extend(position..position + run);
+        }
+        position += run;
+    }
+    sectors
+}
+
+pub fn sectors_to_bitfield(sectors: &[u64]) -> Vec<u64> {
+    let mut sectors = sectors.to_vec();
+    sectors.sort_unstable();
+    sectors.dedup();
+
+    let mut runs = Vec::new();
+    let mut position = 0;
+    let mut iter = sectors.into_iter().peekable();
+    while let Some(start) = iter.next() {
+        let mut end = start + 1;
+        while iter.peek() == Some(&end) {
+            iter.next();
+            end += 1;
+        }
+        runs.push(start - position);
+        runs.push(end - start);
+        position = end;
+    }
+    runs
+}
@@ -0,0 +1,22 @@
+use fvm_shared::{address::Address, econ::TokenAmount};
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct MsigTransaction {
+    #[serde(rename = "ID")]
+    pub id: i64,
+    #[serde(with = "json::address")]
+    pub to: Address,
+    #[serde(with = "json::token_amount")]
+    pub value: TokenAmount,
+    pub method: u64,
+    // Base64 cbor, absent for plain transfers
+    #[serde(default)]
+    pub params: Option<String>,
+    // The proposer always comes first
+    #[serde(with = "json::vec_address", default)]
+    pub approved: Vec<Address>,
+}
@@ -0,0 +1,176 @@
+use cid::Cid;
+use fvm_shared::address::{Address, Error as AddressError, Protocol};
+use fvm_shared_3::address::{Address as Address3, Payload};
+use rpc::RpcError;
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::RwLock};
+
+use crate::{LotusApi, TipsetJson, FINALITY};
+
+// Ethereum address manager namespace of f410 addresses
+pub const EAM_NAMESPACE: u64 = 10;
+// 0xff followed by 11 zero bytes and a big endian actor id
+const MASKED_ID_PREFIX: [u8; 12] = [0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+// Keyed by network name first, one process may talk to mainnet and calibration at once.
+// Only ids and robust addresses of actors created before the finalized tipset are cached,
+// a reorg may give a younger id to another actor
+static ID_ADDRESSES: RwLock<BTreeMap<(String, String), Address>> = RwLock::new(BTreeMap::new());
+static ROBUST_ADDRESSES: RwLock<BTreeMap<(String, u64), AnyAddress>> = RwLock::new(BTreeMap::new());
+
+// fvm_shared 2 cannot carry f4 addresses, so delegated and eth addresses stay apart until resolved to f0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnyAddress {
+    Filecoin(Address),
+    Delegated(String),
+    Eth([u8; 20]),
+}
+
+impl AnyAddress {
+    pub fn eth(&self) -> Option<[u8; 20]> {
+        match self {
+            Self::Eth(eth) => Some(*eth),
+            Self::Delegated(addr) => match Address3::from_str(addr).ok()?.payload() {
+                Payload::Delegated(addr) if addr.namespace() == EAM_NAMESPACE => addr.subaddress().try_into().ok(),
+                _ => None,
+            },
+            Self::Filecoin(addr) => {
+                let mut eth = [0u8; 20];
+                eth[..12].copy_from_slice(&MASKED_ID_PREFIX);
+                eth[12..].copy_from_slice(&addr.id().ok()?.to_be_bytes());
+                Some(eth)
+            }
+        }
+    }
+
+    // Masked ids are plain id addresses, other eth addresses live in the EAM namespace
+    fn normalize(&self) -> Self {
+        match self {
+            Self::Eth(eth) if eth[..12] == MASKED_ID_PREFIX => {
+                Self::Filecoin(Address::new_id(u64::from_be_bytes(eth[12..].try_into().unwrap())))
+            }
+            // A 20 bytes subaddress is always within the delegated address limit
+            Self::Eth(eth) => Self::Delegated(Address3::new_delegated(EAM_NAMESPACE, eth).unwrap().to_string()),
+            addr => addr.clone(),
+        }
+    }
+}
+
+impl From<Address> for AnyAddress {
+    fn from(addr: Address) -> Self {
+        Self::Filecoin(addr)
+    }
+}
+
+impl FromStr for AnyAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(eth) = s.strip_prefix("0x") {
+            return match hex::decode(eth).ok().and_then(|eth| eth.try_into().ok()) {
+                Some(eth) => Ok(Self::Eth(eth)),
+                None => Err(AddressError::InvalidLength),
+            };
+        }
+        if s.get(1..2) == Some("4") {
+            return match Address3::from_str(s) {
+                Ok(_) => Ok(Self::Delegated(s.to_string())),
+                Err(_) => Err(AddressError::InvalidPayload),
+            };
+        }
+        Ok(Self::Filecoin(Address::from_str(s)?))
+    }
+}
+
+impl fmt::Display for AnyAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Filecoin(addr) => write!(f, "{}", addr),
+            Self::Delegated(addr) => write!(f, "{}", addr),
+            Self::Eth(eth) => write!(f, "0x{}", hex::encode(eth)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AddressResolver<A> {
+    api: A,
+}
+
+impl<A: LotusApi> AddressResolver<A> {
+    pub fn new(api: A) -> Self {
+        Self { api }
+    }
+
+    pub async fn id(&self, addr: &AnyAddress) -> Result<Address, RpcError> {
+        let addr = addr.normalize();
+        if let AnyAddress::Filecoin(addr) = addr {
+            if addr.protocol() == Protocol::ID {
+                return Ok(addr);
+            }
+        }
+
+        let key = (self.api.state_network_name().await?, addr.to_string());
+        if let Some(id) = ID_ADDRESSES.read().unwrap().get(&key) {
+            return Ok(*id);
+        }
+
+        let head = self.api.chain_head().await?;
+        let finalized = self.finalized_tipset(&head).await?;
+        if !finalized.is_empty() {
+            if let Ok(id) = self.api.state_lookup_id(&addr, finalized).await {
+                ID_ADDRESSES.write().unwrap().insert(key, id);
+                return Ok(id);
+            }
+        }
+
+        self.api.state_lookup_id(&addr, head.key()).await
+    }
+
+    // Only what the chain already has at head - FINALITY is safe to cache, a reorg cannot change it
+    async fn finalized_tipset(&self, head: &TipsetJson) -> Result<Vec<Cid>, RpcError> {
+        match head.height > FINALITY {
+            true => Ok(self.api.chain_get_tipset_by_height(head.height - FINALITY).await?.key()),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    // Accounts resolve through StateAccountKey, older nodes don't report the robust address of other actors
+    pub async fn robust(&self, addr: &AnyAddress) -> Result<AnyAddress, RpcError> {
+        let id = self.id(addr).await?;
+        let network = self.api.state_network_name().await?;
+        let key = (network, id.id().unwrap_or_default());
+        if let Some(robust) = ROBUST_ADDRESSES.read().unwrap().get(&key) {
+            return Ok(robust.clone());
+        }
+
+        let robust = self.api.state_get_actor(id).await?.address.and_then(|addr| AnyAddress::from_str(&addr).ok());
+        let robust = match robust {
+            Some(AnyAddress::Filecoin(addr)) if addr.protocol() == Protocol::ID => None,
+            robust => robust,
+        };
+        let robust = match robust {
+            Some(robust) => robust,
+            None => AnyAddress::Filecoin(self.api.state_account_key(id).await?),
+        };
+
+        // The pair is cached once the robust address resolves to the same id at the finalized tipset
+        let finalized = self.finalized_tipset(&self.api.chain_head().await?).await?;
+        if !finalized.is_empty() && self.api.state_lookup_id(&robust, finalized).await.ok() == Some(id) {
+            ROBUST_ADDRESSES.write().unwrap().insert(key, robust.clone());
+        }
+        Ok(robust)
+    }
+
+    // Actors deployed through the EAM keep their eth address, any other actor is addressed by its masked id
+    pub async fn eth(&self, addr: &AnyAddress) -> Result<[u8; 20], RpcError> {
+        if let AnyAddress::Filecoin(_) = addr {
+            if let Ok(robust @ AnyAddress::Delegated(_)) = self.robust(addr).await {
+                if let Some(eth) = robust.eth() {
+                    return Ok(eth);
+                }
+            }
+            return Ok(AnyAddress::Filecoin(self.id(addr).await?).eth().unwrap());
+        }
+        addr.eth().ok_or(RpcError::RpcResponseParseError)
+    }
+}
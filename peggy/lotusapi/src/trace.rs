@@ -0,0 +1,137 @@
+use forest_json::cid::CidJson;
+use fvm_shared::{econ::TokenAmount, error::ExitCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{json, AnyAddress, ReceiptJson};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct TraceMessage {
+    #[serde(with = "json::any_address")]
+    pub from: AnyAddress,
+    #[serde(with = "json::any_address")]
+    pub to: AnyAddress,
+    #[serde(with = "json::token_amount")]
+    pub value: TokenAmount,
+    pub method: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct TraceReceipt {
+    pub exit_code: ExitCode,
+    // Only filled before FVM, later traces carry gas charges instead
+    #[serde(default)]
+    pub gas_used: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GasCharge {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "tg")]
+    pub total_gas: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExecutionTrace {
+    pub msg: TraceMessage,
+    pub msg_rct: TraceReceipt,
+    #[serde(default)]
+    pub error: String,
+    #[serde(default)]
+    pub gas_charges: Option<Vec<GasCharge>>,
+    #[serde(default)]
+    pub subcalls: Option<Vec<ExecutionTrace>>,
+}
+
+impl ExecutionTrace {
+    pub fn subcalls(&self) -> &[ExecutionTrace] {
+        self.subcalls.as_deref().unwrap_or_default()
+    }
+
+    pub fn gas_used(&self) -> i64 {
+        match &self.gas_charges {
+            Some(charges) if self.msg_rct.gas_used == 0 => charges.iter().map(|charge| charge.total_gas).sum(),
+            _ => self.msg_rct.gas_used,
+        }
+    }
+
+    // The innermost failed call, an actor usually aborts with the exit code of the send that failed
+    pub fn failure(&self) -> Option<&ExecutionTrace> {
+        if self.msg_rct.exit_code.is_success() {
+            return None;
+        }
+        match self.subcalls().iter().find_map(|call| call.failure()) {
+            Some(call) => Some(call),
+            None => Some(self),
+        }
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:indent$}{} -> {} method {} value {} exit {} gas {}",
+            "",
+            self.msg.from,
+            self.msg.to,
+            self.msg.method,
+            self.msg.value,
+            self.msg_rct.exit_code,
+            self.gas_used(),
+            indent = depth * 2
+        )?;
+        if !self.error.is_empty() {
+            write!(f, ": {}", self.error)?;
+        }
+        for call in self.subcalls() {
+            writeln!(f)?;
+            call.render(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ExecutionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, 0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct InvocResult {
+    pub msg_cid: CidJson,
+    // Filled by StateCall, StateReplay of old nodes may leave it out
+    #[serde(default)]
+    pub msg_rct: Option<ReceiptJson>,
+    pub execution_trace: ExecutionTrace,
+    #[serde(default)]
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_shared::address::Address;
+    use fvm_shared_3::address::Address as Address3;
+    use serde_json::json;
+
+    #[test]
+    fn trace_of_delegated_address_parses() {
+        let contract = Address3::new_delegated(crate::EAM_NAMESPACE, &[0x11; 20]).unwrap().to_string();
+        let trace: ExecutionTrace = serde_json::from_value(json!({
+            "Msg": {"From": "f01234", "To": contract, "Value": "0", "Method": 3844450837u64},
+            "MsgRct": {"ExitCode": 0, "GasUsed": 0},
+            "Error": "",
+            "GasCharges": null,
+            "Subcalls": null
+        }))
+        .unwrap();
+
+        assert_eq!(trace.msg.from, AnyAddress::from(Address::new_id(1234)));
+        assert_eq!(trace.msg.to, AnyAddress::Delegated(contract));
+    }
+}
@@ -0,0 +1,1131 @@
+use async_trait::async_trait;
+use cid::Cid;
+use forest_json::{cid::CidJson, message::json::MessageJson, signed_message::json::SignedMessageJson};
+use forest_message::signed_message::SignedMessage;
+use fvm_ipld_encoding::Cbor;
+use fvm_shared::{
+    address::{Address, Protocol},
+    bigint::BigInt,
+    crypto::signature::Signature,
+    econ::TokenAmount,
+    error::ExitCode,
+    message::Message,
+};
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body,
+    Request,
+    Response,
+    Server,
+};
+use log::{info, warn};
+use lotusapi::{
+    bitfield_to_sectors,
+    sectors_to_bitfield,
+    ActorJson,
+    ActorStateJson,
+    AnyAddress,
+    DeadlineInfo,
+    ExecutionTrace,
+    InvocResult,
+    LotusApi,
+    MessageLookup,
+    MinerInfo,
+    MinerPower,
+    MinerSectors,
+    MsigTransaction,
+    ParentMessage,
+    ReceiptJson,
+    TipsetJson,
+    TraceMessage,
+    TraceReceipt,
+    CHAIN_GET_MESSAGE,
+    CHAIN_GET_PARENT_MESSAGES,
+    CHAIN_GET_PARENT_RECEIPTS,
+    CHAIN_GET_TIPSET,
+    CHAIN_GET_TIPSET_BY_HEIGHT,
+    CHAIN_HEAD,
+    CHAIN_READ_OBJ,
+    GAS_ESTIMATE_MESSAGE_GAS,
+    MPOOL_GET_NONCE,
+    MPOOL_PUSH,
+    MSIG_GET_PENDING,
+    STATE_ACCOUNT_KEY,
+    STATE_CALL,
+    STATE_GET_ACTOR,
+    STATE_LIST_MESSAGES,
+    STATE_LOOKUP_ID,
+    STATE_MINER_AVAILABLE_BALANCE,
+    STATE_MINER_FAULTS,
+    STATE_MINER_INFO,
+    STATE_MINER_POWER,
+    STATE_MINER_PROVING_DEADLINE,
+    STATE_MINER_SECTOR_COUNT,
+    STATE_READ_STATE,
+    STATE_REPLAY,
+    STATE_SEARCH_MSG_LIMITED,
+    STATE_WAIT_MSG_LIMITED,
+    WALLET_BALANCE,
+};
+use rpc::{NodeInfo, RpcEndpoint, RpcError, STATE_NETWORK_NAME, VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    ops::{Add, Mul, Sub},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+const GENESIS_CID: &str = "bafyreibjo4xmgaevkgud7mbifn3dzp4v4lyaui4yvqp3f2bqwtxcjrdqg4";
+const FIRST_ACTOR_ID: u64 = 1000;
+const NETWORK_NAME: &str = "mocknet";
+
+const GAS_LIMIT: i64 = 10_000_000;
+const GAS_FEE_CAP: u64 = 200_000;
+const GAS_PREMIUM: u64 = 100_000;
+// Lotus replaces a pending message only for at least 1.25x its premium
+const RBF_NUM: u64 = 64;
+const RBF_DENOM: u64 = 256;
+
+const WPOST_PROVING_PERIOD: i64 = 2880;
+const WPOST_CHALLENGE_WINDOW: i64 = 60;
+const WPOST_CHALLENGE_LOOKBACK: i64 = 20;
+const FAULT_DECLARATION_CUTOFF: i64 = 70;
+
+#[derive(Error, Debug)]
+pub enum MockError {
+    #[error("hyper server error: {0}")]
+    ServerError(#[from] hyper::Error),
+    #[error("rpc endpoint error: {0}")]
+    RpcEndpointError(#[from] RpcError),
+}
+
+#[derive(Clone)]
+pub struct MockReceipt {
+    pub exit_code: ExitCode,
+    pub return_data: Option<Vec<u8>>,
+    pub return_dec: Value,
+    pub gas_used: i64,
+    pub subcalls: Vec<ExecutionTrace>,
+}
+
+impl Default for MockReceipt {
+    fn default() -> Self {
+        Self {
+            exit_code: ExitCode::OK,
+            return_data: None,
+            return_dec: Value::Null,
+            gas_used: GAS_LIMIT / 2,
+            subcalls: Vec::new(),
+        }
+    }
+}
+
+impl MockReceipt {
+    pub fn with_exit_code(mut self, exit_code: ExitCode) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    pub fn with_return_data(mut self, return_data: Vec<u8>) -> Self {
+        self.return_data = Some(return_data);
+        self
+    }
+
+    pub fn with_return_dec(mut self, return_dec: Value) -> Self {
+        self.return_dec = return_dec;
+        self
+    }
+
+    // Internal sends reported by StateReplay below the message itself
+    pub fn with_subcall(mut self, subcall: ExecutionTrace) -> Self {
+        self.subcalls.push(subcall);
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct ExecutedMessage {
+    pub cid: Cid,
+    pub message: Message,
+    pub receipt: MockReceipt,
+    pub height: i64,
+}
+
+struct RpcFailure {
+    code: i64,
+    message: String,
+}
+
+impl RpcFailure {
+    fn new(message: String) -> Self {
+        Self { code: 1, message }
+    }
+}
+
+pub struct MockChain {
+    height: i64,
+    tipsets: Vec<Cid>,
+    next_id: u64,
+    balances: HashMap<Vec<u8>, TokenAmount>,
+    nonces: HashMap<Vec<u8>, u64>,
+    id_addresses: HashMap<Vec<u8>, Address>,
+    robust_addresses: HashMap<u64, Address>,
+    delegated_addresses: HashMap<String, Address>,
+    // Height each id was assigned at, lookups at older tipsets cannot resolve it
+    created: HashMap<u64, i64>,
+    scripts: HashMap<(Vec<u8>, u64), VecDeque<MockReceipt>>,
+    messages: Vec<ExecutedMessage>,
+    miners: HashMap<Vec<u8>, MockMiner>,
+    actors: HashMap<Vec<u8>, (Cid, Cid)>,
+    objects: HashMap<Cid, Vec<u8>>,
+    msig_pending: HashMap<Vec<u8>, Vec<MsigTransaction>>,
+    forks: u64,
+    // While held, pushed messages wait in the mpool instead of executing right away
+    hold: bool,
+    mpool: Vec<SignedMessage>,
+    // MpoolPush calls, rejected ones included
+    pushes: u64,
+    // Replaced pending message to its replacement, lookups of the replaced cid find the executed one like Lotus does
+    replaced: HashMap<Cid, Cid>,
+}
+
+// Only hashed to derive distinct tipset keys of empty epochs and forks
+#[derive(Serialize, Deserialize)]
+struct MockTipset(Vec<u8>, i64, u64);
+
+impl Cbor for MockTipset {}
+
+#[derive(Clone, Debug)]
+pub struct MockMiner {
+    pub info: MinerInfo,
+    pub power: MinerPower,
+    pub faults: Vec<u64>,
+    pub sectors: MinerSectors,
+}
+
+impl MockMiner {
+    pub fn new(info: MinerInfo) -> Self {
+        Self { info, power: MinerPower::default(), faults: Vec::new(), sectors: MinerSectors::default() }
+    }
+}
+
+impl Default for MockChain {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            tipsets: vec![Cid::from_str(GENESIS_CID).unwrap()],
+            next_id: FIRST_ACTOR_ID,
+            balances: HashMap::new(),
+            nonces: HashMap::new(),
+            id_addresses: HashMap::new(),
+            robust_addresses: HashMap::new(),
+            delegated_addresses: HashMap::new(),
+            created: HashMap::new(),
+            scripts: HashMap::new(),
+            messages: Vec::new(),
+            miners: HashMap::new(),
+            actors: HashMap::new(),
+            objects: HashMap::new(),
+            msig_pending: HashMap::new(),
+            forks: 0,
+            hold: false,
+            mpool: Vec::new(),
+            pushes: 0,
+            replaced: HashMap::new(),
+        }
+    }
+}
+
+impl MockChain {
+    pub fn height(&self) -> i64 {
+        self.height
+    }
+
+    pub fn messages(&self) -> Vec<ExecutedMessage> {
+        self.messages.clone()
+    }
+
+    pub fn balance(&self, addr: Address) -> TokenAmount {
+        let addr = self.resolve(addr).unwrap_or(addr);
+        self.balances.get(&addr.to_bytes()).cloned().unwrap_or_default()
+    }
+
+    pub fn set_balance(&mut self, addr: Address, amount: TokenAmount) -> Address {
+        let id = self.ensure_actor(addr);
+        self.balances.insert(id.to_bytes(), amount);
+        id
+    }
+
+    pub fn register_actor(&mut self, robust: Address, id: Address) {
+        self.id_addresses.insert(robust.to_bytes(), id);
+        if let Ok(id) = id.id() {
+            self.robust_addresses.insert(id, robust);
+            self.created.entry(id).or_insert(self.height);
+        }
+    }
+
+    // f4 addresses cannot be parsed by fvm_shared 2, they are kept as rendered
+    pub fn register_delegated(&mut self, delegated: &str, id: Address) {
+        self.delegated_addresses.insert(delegated.to_string(), id);
+        if let Ok(id) = id.id() {
+            self.created.entry(id).or_insert(self.height);
+        }
+    }
+
+    fn created_at(&self, id: Address, height: i64) -> bool {
+        self.created.get(&id.id().unwrap_or_default()).map(|created| *created <= height).unwrap_or(true)
+    }
+
+    fn delegated_address(&self, id: Address) -> Option<String> {
+        self.delegated_addresses.iter().find(|(_, addr)| **addr == id).map(|(delegated, _)| delegated.clone())
+    }
+
+    // Proposals are not executed by the mock, pending transactions are set by the caller
+    pub fn set_msig_pending(&mut self, msig: Address, txns: Vec<MsigTransaction>) {
+        let msig = self.resolve(msig).unwrap_or(msig);
+        self.msig_pending.insert(msig.to_bytes(), txns);
+    }
+
+    pub fn hold(&mut self) {
+        self.hold = true;
+    }
+
+    // Executes the messages waiting in the mpool in push order
+    pub fn release(&mut self) {
+        self.hold = false;
+        for smsg in std::mem::take(&mut self.mpool) {
+            if let Err(err) = self.push(smsg) {
+                warn!("Mock dropped a pending message: {}", err.message);
+            }
+        }
+    }
+
+    pub fn pending(&self) -> Vec<Message> {
+        self.mpool.iter().map(|smsg| smsg.message().clone()).collect()
+    }
+
+    pub fn pushes(&self) -> u64 {
+        self.pushes
+    }
+
+    // The mock never verifies signatures, callers without keys push messages with an empty one
+    pub fn push_message(&mut self, msg: Message) -> Result<Cid, String> {
+        let smsg = SignedMessage::new_unchecked(msg, Signature::new_secp256k1(Vec::new()));
+        match self.push(smsg) {
+            Ok(cid) => Ok(cid),
+            Err(err) => Err(err.message),
+        }
+    }
+
+    pub fn script(&mut self, to: Address, method_num: u64, receipt: MockReceipt) {
+        let to = self.resolve(to).unwrap_or(to);
+        self.scripts.entry((to.to_bytes(), method_num)).or_default().push_back(receipt);
+    }
+
+    pub fn set_miner(&mut self, miner: Address, state: MockMiner) -> Address {
+        let id = self.ensure_actor(miner);
+        self.miners.insert(id.to_bytes(), state);
+        id
+    }
+
+    pub fn miner(&self, miner: Address) -> Option<MockMiner> {
+        let miner = self.resolve(miner).unwrap_or(miner);
+        self.miners.get(&miner.to_bytes()).cloned()
+    }
+
+    pub fn set_actor_state<T: Cbor>(&mut self, addr: Address, code: Cid, state: &T) -> Address {
+        let id = self.ensure_actor(addr);
+        let head = state.cid().unwrap();
+        self.objects.insert(head, state.marshal_cbor().unwrap());
+        self.actors.insert(id.to_bytes(), (code, head));
+        id
+    }
+
+    fn actor_param(&self, params: &Value) -> Result<(Cid, Cid), RpcFailure> {
+        let addr = param_address(params, 0)?;
+        let id = self.resolve(addr).unwrap_or(addr);
+        match self.actors.get(&id.to_bytes()) {
+            Some(actor) => Ok(*actor),
+            None => Err(RpcFailure::new(format!("actor not found: {}", addr))),
+        }
+    }
+
+    fn miner_param(&self, params: &Value) -> Result<(Address, MockMiner), RpcFailure> {
+        let miner = param_address(params, 0)?;
+        match self.miner(miner) {
+            Some(state) => Ok((miner, state)),
+            None => Err(RpcFailure::new(format!("failed to load miner actor: actor not found: {}", miner))),
+        }
+    }
+
+    fn proving_deadline(&self) -> DeadlineInfo {
+        let period_start = self.height - self.height % WPOST_PROVING_PERIOD;
+        let index = (self.height - period_start) / WPOST_CHALLENGE_WINDOW;
+        let open = period_start + index * WPOST_CHALLENGE_WINDOW;
+        DeadlineInfo {
+            current_epoch: self.height,
+            period_start,
+            index: index as u64,
+            open,
+            close: open + WPOST_CHALLENGE_WINDOW,
+            challenge: open - WPOST_CHALLENGE_LOOKBACK,
+            fault_cutoff: open - FAULT_DECLARATION_CUTOFF,
+        }
+    }
+
+    pub fn advance(&mut self, epochs: i64) {
+        for _ in 0..epochs {
+            let tipset = self.next_tipset();
+            self.tipsets.push(tipset);
+            self.height += 1;
+        }
+    }
+
+    // Replace the last depth tipsets with a fork of the same length, executed messages stay at their heights
+    pub fn reorg(&mut self, depth: i64) {
+        let depth = depth.min(self.height);
+        self.forks += 1;
+        self.tipsets.truncate((self.height - depth + 1) as usize);
+        self.height -= depth;
+        self.advance(depth);
+    }
+
+    fn next_tipset(&self) -> Cid {
+        let parent = self.tipsets.last().unwrap().to_bytes();
+        MockTipset(parent, self.height + 1, self.forks).cid().unwrap()
+    }
+
+    fn find_tipset(&self, key: &[CidJson]) -> Option<i64> {
+        let cid = key.first()?.0;
+        self.tipsets.iter().rposition(|tipset| *tipset == cid).map(|height| height as i64)
+    }
+
+    fn resolve(&self, addr: Address) -> Option<Address> {
+        if addr.protocol() == Protocol::ID {
+            return Some(addr);
+        }
+        self.id_addresses.get(&addr.to_bytes()).cloned()
+    }
+
+    fn ensure_actor(&mut self, addr: Address) -> Address {
+        if let Some(id) = self.resolve(addr) {
+            return id;
+        }
+        let id = Address::new_id(self.next_id);
+        self.next_id += 1;
+        self.register_actor(addr, id);
+        id
+    }
+
+    fn tipset_json(&self, height: i64) -> Value {
+        json!([CidJson(self.tipsets[height as usize])])
+    }
+
+    fn full_tipset_json(&self, height: i64) -> Value {
+        let parents = if height > 0 { self.tipset_json(height - 1) } else { json!([]) };
+        json!({
+            "Cids": self.tipset_json(height),
+            "Blocks": [{"Parents": parents, "Height": height}],
+            "Height": height,
+        })
+    }
+
+    fn receipt_json(receipt: &MockReceipt) -> Value {
+        let return_data = receipt.return_data.as_ref().map(|b| base64::encode_config(b, base64::STANDARD));
+        json!({
+            "ExitCode": receipt.exit_code.value(),
+            "Return": return_data,
+            "GasUsed": receipt.gas_used,
+        })
+    }
+
+    fn lookup_json(&self, msg: &ExecutedMessage) -> Value {
+        json!({
+            "Message": CidJson(msg.cid),
+            "Receipt": Self::receipt_json(&msg.receipt),
+            "ReturnDec": msg.receipt.return_dec,
+            "TipSet": self.tipset_json(msg.height),
+            "Height": msg.height,
+        })
+    }
+
+    fn invoc_json(cid: Cid, msg: &Message, receipt: &MockReceipt) -> Value {
+        let trace = ExecutionTrace {
+            msg: TraceMessage {
+                from: msg.from.into(),
+                to: msg.to.into(),
+                value: msg.value.clone(),
+                method: msg.method_num,
+            },
+            msg_rct: TraceReceipt { exit_code: receipt.exit_code, gas_used: receipt.gas_used },
+            error: String::new(),
+            gas_charges: None,
+            subcalls: Some(receipt.subcalls.clone()),
+        };
+        json!({
+            "MsgCid": CidJson(cid),
+            "MsgRct": Self::receipt_json(receipt),
+            "ExecutionTrace": trace,
+            "Error": "",
+        })
+    }
+
+    fn replay_json(&self, msg: &ExecutedMessage) -> Value {
+        Self::invoc_json(msg.cid, &msg.message, &msg.receipt)
+    }
+
+    // Peeks the scripted receipt without consuming it, the real push still gets it
+    fn call_json(&self, msg: &Message) -> Result<Value, RpcFailure> {
+        let cid = msg.cid().map_err(|err| RpcFailure::new(format!("invalid message: {}", err)))?;
+        let to = self.resolve(msg.to).unwrap_or(msg.to);
+        let receipt = self
+            .scripts
+            .get(&(to.to_bytes(), msg.method_num))
+            .and_then(|receipts| receipts.front().cloned())
+            .unwrap_or_default();
+        Ok(Self::invoc_json(cid, msg, &receipt))
+    }
+
+    fn find_message(&self, cid: &Cid) -> Option<&ExecutedMessage> {
+        self.messages.iter().find(|msg| &msg.cid == cid)
+    }
+
+    fn find_executed(&self, cid: &Cid) -> Option<&ExecutedMessage> {
+        let mut cid = *cid;
+        while let Some(replacement) = self.replaced.get(&cid) {
+            cid = *replacement;
+        }
+        self.find_message(&cid)
+    }
+
+    fn find_pending(&self, cid: &Cid) -> Option<&SignedMessage> {
+        self.mpool.iter().find(|smsg| smsg.cid().ok().as_ref() == Some(cid))
+    }
+
+    fn pending_count(&self, from: Address) -> u64 {
+        self.mpool.iter().filter(|smsg| self.resolve(smsg.message().from) == Some(from)).count() as u64
+    }
+
+    // A message of the same sender and nonce is only replaced for a high enough premium
+    fn hold_message(&mut self, from: Address, nonce: u64, smsg: SignedMessage) -> Result<Cid, RpcFailure> {
+        let cid = smsg.cid().map_err(|err| RpcFailure::new(format!("invalid message: {}", err)))?;
+        let msg = smsg.message();
+        let replaced = self.mpool.iter().position(|pending| {
+            self.resolve(pending.message().from) == Some(from) && pending.message().sequence == msg.sequence
+        });
+        match replaced {
+            Some(index) => {
+                let premium = self.mpool[index].message().gas_premium.atto();
+                let min_premium = premium + premium * RBF_NUM / RBF_DENOM + 1u64;
+                if msg.gas_premium.atto() < &min_premium {
+                    return Err(RpcFailure::new(format!(
+                        "replace by fee has too low GasPremium: {} < {}",
+                        msg.gas_premium.atto(),
+                        min_premium
+                    )));
+                }
+                if let Ok(replaced) = self.mpool[index].cid() {
+                    self.replaced.insert(replaced, cid);
+                }
+                self.mpool[index] = smsg;
+            }
+            None => {
+                let next = nonce + self.pending_count(from);
+                if msg.sequence != next {
+                    return Err(RpcFailure::new(format!("nonce gap: expected {} got {}", next, msg.sequence)));
+                }
+                self.mpool.push(smsg);
+            }
+        }
+        Ok(cid)
+    }
+
+    // Every mock tipset has a single block whose parent messages are the ones executed at its height
+    fn parent_messages(&self, params: &Value) -> Result<Vec<&ExecutedMessage>, RpcFailure> {
+        let CidJson(block) = param_json::<CidJson>(params, 0)?;
+        match self.find_tipset(&[CidJson(block)]) {
+            Some(height) => Ok(self.messages.iter().filter(|msg| msg.height == height).collect()),
+            None => Err(RpcFailure::new(format!("blockstore: block not found: {}", block))),
+        }
+    }
+
+    fn push(&mut self, smsg: SignedMessage) -> Result<Cid, RpcFailure> {
+        let cid = smsg.cid().map_err(|err| RpcFailure::new(format!("invalid message: {}", err)))?;
+        if self.find_message(&cid).is_some() || self.find_pending(&cid).is_some() {
+            return Err(RpcFailure::new("message already in mpool".to_string()));
+        }
+
+        let msg = smsg.message().clone();
+        let from = self.resolve(msg.from).ok_or_else(|| RpcFailure::new(format!("actor not found: {}", msg.from)))?;
+
+        let nonce = self.nonces.get(&from.to_bytes()).cloned().unwrap_or_default();
+        if msg.sequence < nonce {
+            return Err(RpcFailure::new(format!("minimum expected nonce is {}: message nonce too low", nonce)));
+        }
+        if self.hold {
+            return self.hold_message(from, nonce, smsg);
+        }
+        if msg.sequence > nonce {
+            return Err(RpcFailure::new(format!("nonce gap: expected {} got {}", nonce, msg.sequence)));
+        }
+
+        let balance = self.balance(from);
+        let gas_cost = msg.gas_fee_cap.clone().mul(BigInt::from(msg.gas_limit));
+        let required = gas_cost.clone().add(msg.value.clone());
+        if balance < required {
+            return Err(RpcFailure::new(format!("not enough funds ({} < {}): insufficient funds", balance, required)));
+        }
+
+        let to = self.ensure_actor(msg.to);
+        let receipt = self
+            .scripts
+            .get_mut(&(to.to_bytes(), msg.method_num))
+            .and_then(|receipts| receipts.pop_front())
+            .unwrap_or_default();
+
+        let mut spent = gas_cost;
+        if receipt.exit_code == ExitCode::OK {
+            spent = spent.add(msg.value.clone());
+            let to_balance = self.balance(to).add(msg.value.clone());
+            self.balances.insert(to.to_bytes(), to_balance);
+        }
+        self.balances.insert(from.to_bytes(), balance.sub(spent));
+        self.nonces.insert(from.to_bytes(), nonce + 1);
+
+        self.tipsets.push(cid);
+        self.height += 1;
+
+        info!("Mock executed {} at {} ({} -> {} method {})", cid, self.height, msg.from, msg.to, msg.method_num);
+        self.messages.push(ExecutedMessage { cid, message: msg, receipt, height: self.height });
+
+        Ok(cid)
+    }
+
+    fn handle(&mut self, method: &str, params: Value) -> Result<Value, RpcFailure> {
+        match method {
+            "Filecoin.Version" => Ok(json!({"Version": "1.19.0+mock", "APIVersion": 0x00010500, "BlockDelay": 30})),
+            "Filecoin.ChainHead" => Ok(self.full_tipset_json(self.height)),
+            "Filecoin.ChainGetTipSet" => {
+                let key = param_json::<Vec<CidJson>>(&params, 0)?;
+                match self.find_tipset(&key) {
+                    Some(height) => Ok(self.full_tipset_json(height)),
+                    None => Err(RpcFailure::new("loading tipset: block not found".to_string())),
+                }
+            }
+            "Filecoin.ChainGetTipSetByHeight" => {
+                let height = param_json::<i64>(&params, 0)?;
+                match height >= 0 && height <= self.height {
+                    true => Ok(self.full_tipset_json(height)),
+                    false => Err(RpcFailure::new(format!(
+                        "looking for tipset with height greater than start point, req: {}, head: {}",
+                        height, self.height
+                    ))),
+                }
+            }
+            "Filecoin.MpoolGetNonce" => {
+                let addr = param_address(&params, 0)?;
+                let addr = self.resolve(addr).unwrap_or(addr);
+                let nonce = self.nonces.get(&addr.to_bytes()).cloned().unwrap_or_default();
+                Ok(json!(nonce + self.pending_count(addr)))
+            }
+            "Filecoin.WalletBalance" => {
+                let addr = param_address(&params, 0)?;
+                Ok(json!(self.balance(addr).atto().to_string()))
+            }
+            "Filecoin.GasEstimateMessageGas" => {
+                let MessageJson(mut msg) = param_json::<MessageJson>(&params, 0)?;
+                msg.gas_limit = GAS_LIMIT;
+                msg.gas_fee_cap = TokenAmount::from_atto(GAS_FEE_CAP);
+                msg.gas_premium = TokenAmount::from_atto(GAS_PREMIUM);
+                Ok(json!(MessageJson(msg)))
+            }
+            "Filecoin.MpoolPush" => {
+                self.pushes += 1;
+                let SignedMessageJson(smsg) = param_json::<SignedMessageJson>(&params, 0)?;
+                let cid = self.push(smsg)?;
+                Ok(json!(CidJson(cid)))
+            }
+            "Filecoin.ChainGetMessage" => {
+                let CidJson(cid) = param_json::<CidJson>(&params, 0)?;
+                // Lotus stores pushed messages right away, pending ones are found too
+                let msg = match self.find_message(&cid) {
+                    Some(msg) => Some(msg.message.clone()),
+                    None => self.find_pending(&cid).map(|smsg| smsg.message().clone()),
+                };
+                match msg {
+                    Some(msg) => Ok(json!(MessageJson(msg))),
+                    None => Err(RpcFailure::new(format!("blockstore: block not found: {}", cid))),
+                }
+            }
+            "Filecoin.ChainGetParentMessages" => {
+                let msgs = self.parent_messages(&params)?;
+                Ok(json!(msgs
+                    .iter()
+                    .map(|msg| json!({"Cid": CidJson(msg.cid), "Message": MessageJson(msg.message.clone())}))
+                    .collect::<Vec<Value>>()))
+            }
+            "Filecoin.ChainGetParentReceipts" => {
+                let msgs = self.parent_messages(&params)?;
+                Ok(json!(msgs.iter().map(|msg| Self::receipt_json(&msg.receipt)).collect::<Vec<Value>>()))
+            }
+            "Filecoin.StateListMessages" => {
+                let to = params.get(0).and_then(|filter| filter.get("To")).cloned().unwrap_or(Value::Null);
+                let to = param_address(&json!([to]), 0)?;
+                let to = self.resolve(to).unwrap_or(to);
+                let from_height = param_json::<i64>(&params, 2)?;
+                Ok(json!(self
+                    .messages
+                    .iter()
+                    .filter(|msg| msg.height >= from_height && self.resolve(msg.message.to) == Some(to))
+                    .map(|msg| CidJson(msg.cid))
+                    .collect::<Vec<CidJson>>()))
+            }
+            "Filecoin.StateWaitMsg" | "Filecoin.StateWaitMsgLimited" => {
+                let CidJson(cid) = param_json::<CidJson>(&params, 0)?;
+                match self.find_executed(&cid) {
+                    Some(msg) => Ok(self.lookup_json(msg)),
+                    None => Err(RpcFailure::new(format!("message {} not found", cid))),
+                }
+            }
+            "Filecoin.StateSearchMsg" | "Filecoin.StateSearchMsgLimited" => {
+                let CidJson(cid) = param_json::<CidJson>(&params, 0)?;
+                match self.find_executed(&cid) {
+                    Some(msg) => Ok(self.lookup_json(msg)),
+                    None => Ok(Value::Null),
+                }
+            }
+            "Filecoin.StateReplay" => {
+                let CidJson(cid) = param_json::<CidJson>(&params, 1)?;
+                match self.find_message(&cid) {
+                    Some(msg) => Ok(self.replay_json(msg)),
+                    None => Err(RpcFailure::new(format!("replay: message {} not found", cid))),
+                }
+            }
+            "Filecoin.StateCall" => {
+                let MessageJson(msg) = param_json::<MessageJson>(&params, 0)?;
+                self.call_json(&msg)
+            }
+            "Filecoin.StateNetworkName" => Ok(json!(NETWORK_NAME)),
+            "Filecoin.StateLookupID" => {
+                let key = param_json::<Vec<CidJson>>(&params, 1)?;
+                let height = match key.is_empty() {
+                    true => self.height,
+                    false => match self.find_tipset(&key) {
+                        Some(height) => height,
+                        None => return Err(RpcFailure::new("loading tipset: block not found".to_string())),
+                    },
+                };
+                let delegated = param_json::<String>(&params, 0)?;
+                let id = match self.delegated_addresses.get(&delegated) {
+                    Some(id) => Some(*id),
+                    None => self.resolve(param_address(&params, 0)?),
+                };
+                match id {
+                    Some(id) if self.created_at(id, height) => Ok(json!(id.to_string())),
+                    _ => Err(RpcFailure::new(format!("resolution lookup failed ({}): actor not found", delegated))),
+                }
+            }
+            "Filecoin.StateAccountKey" => {
+                let addr = param_address(&params, 0)?;
+                if addr.protocol() != Protocol::ID {
+                    return Ok(json!(addr.to_string()));
+                }
+                match self.robust_addresses.get(&addr.id().unwrap_or_default()) {
+                    Some(robust) => Ok(json!(robust.to_string())),
+                    None => Err(RpcFailure::new(format!("actor not found: {}", addr))),
+                }
+            }
+            "Filecoin.StateGetActor" => {
+                let addr = param_address(&params, 0)?;
+                let id = match self.resolve(addr) {
+                    Some(id) => id,
+                    None => return Err(RpcFailure::new(format!("actor not found: {}", addr))),
+                };
+                // Actors without scripted state get the genesis cid as placeholder code and head
+                let genesis = Cid::from_str(GENESIS_CID).unwrap();
+                let (code, head) = self.actors.get(&id.to_bytes()).cloned().unwrap_or((genesis, genesis));
+                let robust = match self.delegated_address(id) {
+                    Some(delegated) => delegated,
+                    None => self.robust_addresses.get(&id.id().unwrap_or_default()).unwrap_or(&id).to_string(),
+                };
+                Ok(json!({
+                    "Code": CidJson(code),
+                    "Head": CidJson(head),
+                    "Nonce": self.nonces.get(&id.to_bytes()).cloned().unwrap_or_default(),
+                    "Balance": self.balance(id).atto().to_string(),
+                    "Address": robust,
+                }))
+            }
+            // Lotus only renders states of builtin actors, custom actors come back without one and are read through ChainReadObj
+            "Filecoin.StateReadState" => {
+                let (code, _) = self.actor_param(&params)?;
+                let balance = self.balance(param_address(&params, 0)?);
+                Ok(json!({"Balance": balance.atto().to_string(), "Code": CidJson(code), "State": null}))
+            }
+            "Filecoin.ChainReadObj" => {
+                let CidJson(cid) = param_json::<CidJson>(&params, 0)?;
+                match self.objects.get(&cid) {
+                    Some(obj) => Ok(json!(base64::encode_config(obj, base64::STANDARD))),
+                    None => Err(RpcFailure::new(format!("blockstore: block not found: {}", cid))),
+                }
+            }
+            "Filecoin.StateMinerInfo" => Ok(json!(self.miner_param(&params)?.1.info)),
+            "Filecoin.MsigGetPending" => {
+                let msig = param_address(&params, 0)?;
+                let msig = self.resolve(msig).unwrap_or(msig);
+                Ok(json!(self.msig_pending.get(&msig.to_bytes()).cloned().unwrap_or_default()))
+            }
+            "Filecoin.StateMinerPower" => Ok(json!(self.miner_param(&params)?.1.power)),
+            "Filecoin.StateMinerAvailableBalance" => {
+                let (miner, _) = self.miner_param(&params)?;
+                Ok(json!(self.balance(miner).atto().to_string()))
+            }
+            "Filecoin.StateMinerFaults" => Ok(json!(sectors_to_bitfield(&self.miner_param(&params)?.1.faults))),
+            "Filecoin.StateMinerProvingDeadline" => {
+                self.miner_param(&params)?;
+                Ok(json!(self.proving_deadline()))
+            }
+            "Filecoin.StateMinerSectorCount" => Ok(json!(self.miner_param(&params)?.1.sectors)),
+            _ => {
+                warn!("Mock does not implement {}", method);
+                Err(RpcFailure { code: -32601, message: format!("method '{}' not found", method) })
+            }
+        }
+    }
+}
+
+fn param_json<T: for<'de> serde::Deserialize<'de>>(params: &Value, index: usize) -> Result<T, RpcFailure> {
+    let param = params.get(index).cloned().unwrap_or(Value::Null);
+    serde_json::from_value::<T>(param).map_err(|err| RpcFailure::new(format!("invalid param {}: {}", index, err)))
+}
+
+fn param_address(params: &Value, index: usize) -> Result<Address, RpcFailure> {
+    let addr = param_json::<String>(params, index)?;
+    Address::from_str(&addr).map_err(|err| RpcFailure::new(format!("invalid address {}: {}", addr, err)))
+}
+
+fn handle_request(chain: &Mutex<MockChain>, req: Value) -> Value {
+    let id = req.get("id").cloned().unwrap_or(Value::Null);
+    let method = req.get("method").and_then(|m| m.as_str()).unwrap_or_default().to_string();
+    let params = req.get("params").cloned().unwrap_or_else(|| json!([]));
+
+    match chain.lock().unwrap().handle(&method, params) {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(err) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": err.code, "message": err.message}}),
+    }
+}
+
+async fn serve(chain: Arc<Mutex<MockChain>>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let body = match body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(err) => return Ok(Response::builder().status(400).body(Body::from(err.to_string())).unwrap()),
+    };
+
+    let res = match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Array(reqs)) => Value::Array(reqs.into_iter().map(|req| handle_request(&chain, req)).collect()),
+        Ok(req) => handle_request(&chain, req),
+        Err(err) => json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": err.to_string()}}),
+    };
+
+    Ok(Response::builder().header("Content-Type", "application/json").body(Body::from(res.to_string())).unwrap())
+}
+
+pub struct MockLotus {
+    addr: SocketAddr,
+    chain: Arc<Mutex<MockChain>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockLotus {
+    pub async fn start() -> Result<Self, MockError> {
+        let chain = Arc::new(Mutex::new(MockChain::default()));
+
+        let service_chain = chain.clone();
+        let make_svc = make_service_fn(move |_| {
+            let chain = service_chain.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| serve(chain.clone(), req))) }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_svc);
+        let addr = server.local_addr();
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = server.with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                warn!("Mock lotus stopped: {}", err);
+            }
+        });
+
+        info!("Mock lotus listening on {}", addr);
+        Ok(Self { addr, chain, shutdown: Some(tx) })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/rpc/v0", self.addr)
+    }
+
+    pub fn endpoint(&self) -> Result<RpcEndpoint, MockError> {
+        Ok(RpcEndpoint::new(&self.url(), "")?)
+    }
+
+    pub fn chain(&self) -> Arc<Mutex<MockChain>> {
+        self.chain.clone()
+    }
+
+    pub fn api(&self) -> MockApi {
+        MockApi { chain: self.chain.clone(), network: None }
+    }
+
+    pub fn set_balance(&self, addr: Address, amount: TokenAmount) -> Address {
+        self.chain.lock().unwrap().set_balance(addr, amount)
+    }
+
+    pub fn register_actor(&self, robust: Address, id: Address) {
+        self.chain.lock().unwrap().register_actor(robust, id)
+    }
+
+    pub fn register_delegated(&self, delegated: &str, id: Address) {
+        self.chain.lock().unwrap().register_delegated(delegated, id)
+    }
+
+    pub fn set_msig_pending(&self, msig: Address, txns: Vec<MsigTransaction>) {
+        self.chain.lock().unwrap().set_msig_pending(msig, txns)
+    }
+
+    pub fn script(&self, to: Address, method_num: u64, receipt: MockReceipt) {
+        self.chain.lock().unwrap().script(to, method_num, receipt)
+    }
+
+    pub fn hold(&self) {
+        self.chain.lock().unwrap().hold()
+    }
+
+    pub fn release(&self) {
+        self.chain.lock().unwrap().release()
+    }
+
+    pub fn set_miner(&self, miner: Address, state: MockMiner) -> Address {
+        self.chain.lock().unwrap().set_miner(miner, state)
+    }
+
+    pub fn set_actor_state<T: Cbor>(&self, addr: Address, code: Cid, state: &T) -> Address {
+        self.chain.lock().unwrap().set_actor_state(addr, code, state)
+    }
+
+    pub fn advance(&self, epochs: i64) {
+        self.chain.lock().unwrap().advance(epochs)
+    }
+
+    pub fn reorg(&self, depth: i64) {
+        self.chain.lock().unwrap().reorg(depth)
+    }
+
+    pub fn messages(&self) -> Vec<ExecutedMessage> {
+        self.chain.lock().unwrap().messages()
+    }
+}
+
+impl Drop for MockLotus {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockApi {
+    chain: Arc<Mutex<MockChain>>,
+    network: Option<String>,
+}
+
+impl MockApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn network(mut self, network: &str) -> Self {
+        self.network = Some(network.to_string());
+        self
+    }
+
+    pub fn chain(&self) -> Arc<Mutex<MockChain>> {
+        self.chain.clone()
+    }
+
+    fn call<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: T1,
+    ) -> Result<T2, RpcError> {
+        let res = handle_request(&self.chain, json!({"jsonrpc": "2.0", "id": 0, "method": method, "params": params}));
+        match res.get("result") {
+            Some(result) => Ok(serde_json::from_value::<T2>(result.clone())?),
+            None => Err(RpcError::from_application_error(res.get("error").cloned().unwrap_or(Value::Null))),
+        }
+    }
+}
+
+#[async_trait]
+impl LotusApi for MockApi {
+    fn expected_network(&self) -> Option<String> {
+        self.network.clone()
+    }
+
+    async fn version(&self) -> Result<NodeInfo, RpcError> {
+        Ok(NodeInfo::parse(&self.call::<_, Value>(VERSION, json!([]))?))
+    }
+
+    async fn mpool_get_nonce(&self, addr: Address) -> Result<u64, RpcError> {
+        self.call(MPOOL_GET_NONCE, vec![addr.to_string()])
+    }
+
+    async fn mpool_push(&self, smsg: SignedMessage) -> Result<Cid, RpcError> {
+        let CidJson(cid) = self.call(MPOOL_PUSH, vec![SignedMessageJson(smsg)])?;
+        Ok(cid)
+    }
+
+    async fn gas_estimate_message_gas(&self, msg: Message, max_fee: TokenAmount) -> Result<Message, RpcError> {
+        let MessageJson(msg) =
+            self.call(GAS_ESTIMATE_MESSAGE_GAS, json!([MessageJson(msg), {"MaxFee": max_fee.atto().to_string()}, []]))?;
+        Ok(msg)
+    }
+
+    async fn wallet_balance(&self, addr: Address) -> Result<TokenAmount, RpcError> {
+        let balance = self.call::<_, String>(WALLET_BALANCE, vec![addr.to_string()])?;
+        let balance = BigInt::from_str(&balance).map_err(|_| RpcError::RpcResponseParseError)?;
+        Ok(TokenAmount::from_atto(balance))
+    }
+
+    async fn chain_head_height(&self) -> Result<i64, RpcError> {
+        Ok(self.chain_head().await?.height)
+    }
+
+    async fn chain_head(&self) -> Result<TipsetJson, RpcError> {
+        self.call(CHAIN_HEAD, json!([]))
+    }
+
+    async fn chain_get_tipset(&self, key: Vec<Cid>) -> Result<TipsetJson, RpcError> {
+        let key = key.into_iter().map(CidJson).collect::<Vec<CidJson>>();
+        self.call(CHAIN_GET_TIPSET, json!([key]))
+    }
+
+    async fn chain_get_tipset_by_height(&self, height: i64) -> Result<TipsetJson, RpcError> {
+        self.call(CHAIN_GET_TIPSET_BY_HEIGHT, json!([height, []]))
+    }
+
+    async fn chain_get_message(&self, cid: Cid) -> Result<Message, RpcError> {
+        let MessageJson(msg) = self.call(CHAIN_GET_MESSAGE, json!([CidJson(cid)]))?;
+        Ok(msg)
+    }
+
+    async fn chain_get_parent_messages(&self, block: Cid) -> Result<Vec<ParentMessage>, RpcError> {
+        self.call(CHAIN_GET_PARENT_MESSAGES, json!([CidJson(block)]))
+    }
+
+    async fn chain_get_parent_receipts(&self, block: Cid) -> Result<Vec<ReceiptJson>, RpcError> {
+        self.call(CHAIN_GET_PARENT_RECEIPTS, json!([CidJson(block)]))
+    }
+
+    async fn state_list_messages(&self, to: Address, from_height: i64) -> Result<Vec<Cid>, RpcError> {
+        let cids =
+            self.call::<_, Vec<CidJson>>(STATE_LIST_MESSAGES, json!([{"To": to.to_string()}, [], from_height]))?;
+        Ok(cids.into_iter().map(|cid| cid.0).collect())
+    }
+
+    async fn state_wait_msg(
+        &self,
+        cid: Cid,
+        confidence: i64,
+        lookback: i64,
+        _allow_replaced: bool,
+    ) -> Result<MessageLookup, RpcError> {
+        self.call(STATE_WAIT_MSG_LIMITED, json!([CidJson(cid), confidence, lookback]))
+    }
+
+    async fn state_search_msg(
+        &self,
+        cid: Cid,
+        lookback: i64,
+        _allow_replaced: bool,
+    ) -> Result<Option<MessageLookup>, RpcError> {
+        self.call(STATE_SEARCH_MSG_LIMITED, json!([CidJson(cid), lookback]))
+    }
+
+    async fn state_network_name(&self) -> Result<String, RpcError> {
+        self.call(STATE_NETWORK_NAME, json!([]))
+    }
+
+    async fn state_lookup_id(&self, addr: &AnyAddress, tipset: Vec<Cid>) -> Result<Address, RpcError> {
+        let tipset = tipset.into_iter().map(CidJson).collect::<Vec<CidJson>>();
+        let addr = self.call::<_, String>(STATE_LOOKUP_ID, json!([addr.to_string(), tipset]))?;
+        Address::from_str(&addr).map_err(|_| RpcError::RpcResponseParseError)
+    }
+
+    async fn state_account_key(&self, addr: Address) -> Result<Address, RpcError> {
+        let addr = self.call::<_, String>(STATE_ACCOUNT_KEY, json!([addr.to_string(), []]))?;
+        Address::from_str(&addr).map_err(|_| RpcError::RpcResponseParseError)
+    }
+
+    async fn state_get_actor(&self, addr: Address) -> Result<ActorJson, RpcError> {
+        self.call(STATE_GET_ACTOR, json!([addr.to_string(), []]))
+    }
+
+    async fn state_read_state(&self, addr: Address) -> Result<ActorStateJson, RpcError> {
+        self.call(STATE_READ_STATE, json!([addr.to_string(), []]))
+    }
+
+    async fn chain_read_obj(&self, cid: Cid) -> Result<Vec<u8>, RpcError> {
+        let obj = self.call::<_, String>(CHAIN_READ_OBJ, json!([CidJson(cid)]))?;
+        base64::decode_config(obj, base64::STANDARD).map_err(|_| RpcError::RpcResponseParseError)
+    }
+
+    async fn state_replay(&self, cid: Cid) -> Result<InvocResult, RpcError> {
+        self.call(STATE_REPLAY, json!([[], CidJson(cid)]))
+    }
+
+    async fn state_call(&self, msg: Message) -> Result<InvocResult, RpcError> {
+        self.call(STATE_CALL, json!([MessageJson(msg), []]))
+    }
+
+    async fn state_miner_info(&self, miner: Address) -> Result<MinerInfo, RpcError> {
+        self.call(STATE_MINER_INFO, json!([miner.to_string(), []]))
+    }
+
+    async fn msig_get_pending(&self, msig: Address) -> Result<Vec<MsigTransaction>, RpcError> {
+        self.call(MSIG_GET_PENDING, json!([msig.to_string(), []]))
+    }
+
+    async fn state_miner_power(&self, miner: Address) -> Result<MinerPower, RpcError> {
+        self.call(STATE_MINER_POWER, json!([miner.to_string(), []]))
+    }
+
+    async fn state_miner_available_balance(&self, miner: Address) -> Result<TokenAmount, RpcError> {
+        let balance = self.call::<_, String>(STATE_MINER_AVAILABLE_BALANCE, json!([miner.to_string(), []]))?;
+        let balance = BigInt::from_str(&balance).map_err(|_| RpcError::RpcResponseParseError)?;
+        Ok(TokenAmount::from_atto(balance))
+    }
+
+    async fn state_miner_faults(&self, miner: Address) -> Result<Vec<u64>, RpcError> {
+        let faults = self.call::<_, Vec<u64>>(STATE_MINER_FAULTS, json!([miner.to_string(), []]))?;
+        Ok(bitfield_to_sectors(&faults))
+    }
+
+    async fn state_miner_proving_deadline(&self, miner: Address) -> Result<DeadlineInfo, RpcError> {
+        self.call(STATE_MINER_PROVING_DEADLINE, json!([miner.to_string(), []]))
+    }
+
+    async fn state_miner_sector_count(&self, miner: Address) -> Result<MinerSectors, RpcError> {
+        self.call(STATE_MINER_SECTOR_COUNT, json!([miner.to_string(), []]))
+    }
+}
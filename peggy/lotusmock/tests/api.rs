@@ -0,0 +1,23 @@
+use fvm_shared::{address::Address, crypto::signature::SignatureType, econ::TokenAmount};
+use lotusapi::LotusApi;
+use lotusmock::MockApi;
+use state::{lookup_id, wait_msg};
+
+// MockApi serves the typed calls in process, no server nor json-rpc transport is involved
+#[tokio::test]
+async fn push_and_wait_through_mock_api() {
+    let api = MockApi::new();
+    let (from, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    let from_id = api.chain().lock().unwrap().set_balance(from, TokenAmount::from_whole(10));
+    let to = Address::new_id(1500);
+
+    assert_eq!(lookup_id(api.clone(), from).await.unwrap(), from_id);
+    assert_eq!(api.mpool_get_nonce(from).await.unwrap(), 0);
+
+    let cid = mpool::mpool_push(api.clone(), from, key.key_info, to, 0, TokenAmount::from_whole(1), ()).await.unwrap();
+    wait_msg::<_, ()>(api.clone(), cid).await.unwrap();
+
+    assert_eq!(api.mpool_get_nonce(from).await.unwrap(), 1);
+    assert_eq!(api.wallet_balance(to).await.unwrap(), TokenAmount::from_whole(1));
+    assert!(api.wallet_balance(from).await.unwrap() < TokenAmount::from_whole(9));
+}
@@ -0,0 +1,226 @@
+use cid::Cid;
+use forest_json::cid::CidJson;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::{
+    address::Address,
+    bigint::BigInt,
+    crypto::signature::SignatureType,
+    econ::TokenAmount,
+    error::ExitCode,
+    sector::RegisteredPoStProof,
+};
+use libp2p::PeerId;
+use lotusapi::MsigTransaction;
+use lotusmock::{ExecutedMessage, MockLotus, MockReceipt};
+use serde_json::json;
+use std::{
+    ops::{Add, Mul, Sub},
+    str::FromStr,
+};
+
+use actor::ActorError;
+
+const INIT_ACTOR: u64 = 1;
+const STORAGE_POWER_ACTOR: u64 = 4;
+// Any valid cid stands for the installed code, the mock never runs it
+const CODE_CID: &str = "bafyreibjo4xmgaevkgud7mbifn3dzp4v4lyaui4yvqp3f2bqwtxcjrdqg4";
+
+struct Account {
+    address: Address,
+    key_info: forest_key_management::KeyInfo,
+}
+
+async fn funded_account(lotus: &MockLotus) -> Account {
+    let (address, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    lotus.set_balance(address, TokenAmount::from_whole(100));
+    Account { address, key_info: key.key_info }
+}
+
+fn gas_cost(msg: &ExecutedMessage) -> TokenAmount {
+    msg.message.gas_fee_cap.clone().mul(BigInt::from(msg.message.gas_limit))
+}
+
+#[tokio::test]
+async fn create_miner_returns_the_scripted_addresses() {
+    let lotus = MockLotus::start().await.unwrap();
+    let owner = funded_account(&lotus).await;
+    let robust = Address::new_actor(b"created-miner");
+    lotus.script(
+        Address::new_id(STORAGE_POWER_ACTOR),
+        2,
+        MockReceipt::default().with_return_dec(json!({"IDAddress": "f01100", "RobustAddress": robust.to_string()})),
+    );
+
+    let (id_address, robust_address) = miner::create_miner(
+        lotus.endpoint().unwrap(),
+        owner.address,
+        owner.key_info.clone(),
+        owner.address,
+        RegisteredPoStProof::StackedDRGWindow32GiBV1,
+        PeerId::random(),
+    )
+    .await
+    .unwrap();
+    assert_eq!((id_address, robust_address), (Address::new_id(1100), robust));
+
+    let messages = lotus.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].message.to, Address::new_id(STORAGE_POWER_ACTOR));
+    assert_eq!(messages[0].message.method_num, 2);
+    assert_eq!(
+        lotus.chain().lock().unwrap().balance(owner.address),
+        TokenAmount::from_whole(100).sub(gas_cost(&messages[0]))
+    );
+}
+
+#[tokio::test]
+async fn install_and_create_actor() {
+    let lotus = MockLotus::start().await.unwrap();
+    let owner = funded_account(&lotus).await;
+    let code = Cid::from_str(CODE_CID).unwrap();
+    let robust = Address::new_actor(b"owner-actor");
+    lotus.script(
+        Address::new_id(INIT_ACTOR),
+        4,
+        MockReceipt::default().with_return_dec(json!({"CodeCid": CidJson(code), "Installed": true})),
+    );
+    lotus.script(
+        Address::new_id(INIT_ACTOR),
+        2,
+        MockReceipt::default().with_return_dec(json!({"IDAddress": "f01200", "RobustAddress": robust.to_string()})),
+    );
+
+    let wasm = std::env::temp_dir().join(format!("lotusmock-actor-{}.wasm", std::process::id()));
+    std::fs::write(&wasm, b"\0asm").unwrap();
+    let (code_cid, installed) =
+        actor::install_actor(lotus.endpoint().unwrap(), owner.address, owner.key_info.clone(), wasm.clone())
+            .await
+            .unwrap();
+    std::fs::remove_file(wasm).unwrap();
+    assert_eq!((code_cid.0, installed), (code, true));
+
+    let (id_address, robust_address) =
+        actor::create_actor(lotus.endpoint().unwrap(), owner.address, owner.key_info.clone(), code_cid).await.unwrap();
+    assert_eq!((id_address, robust_address), (Address::new_id(1200), robust));
+
+    let messages = lotus.messages();
+    let methods = messages.iter().map(|msg| (msg.message.to, msg.message.method_num)).collect::<Vec<_>>();
+    assert_eq!(methods, vec![(Address::new_id(INIT_ACTOR), 4), (Address::new_id(INIT_ACTOR), 2)]);
+    assert_eq!(messages[1].message.sequence, messages[0].message.sequence + 1);
+}
+
+#[tokio::test]
+async fn take_owner_and_withdraw_miner() {
+    let lotus = MockLotus::start().await.unwrap();
+    let owner = funded_account(&lotus).await;
+    let actor_id = Address::new_id(1300);
+    let miner_id = Address::new_id(1301);
+
+    actor::take_owner(lotus.endpoint().unwrap(), owner.address, owner.key_info.clone(), actor_id, miner_id)
+        .await
+        .unwrap();
+    actor::withdraw_miner(
+        lotus.endpoint().unwrap(),
+        owner.address,
+        owner.key_info.clone(),
+        actor_id,
+        miner_id,
+        TokenAmount::from_whole(5),
+    )
+    .await
+    .unwrap();
+
+    let messages = lotus.messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!((messages[0].message.to, messages[0].message.method_num), (actor_id, 16));
+    assert_eq!(RawBytes::deserialize::<Address>(&messages[0].message.params).unwrap(), miner_id);
+    assert_eq!((messages[1].message.to, messages[1].message.method_num), (actor_id, 19));
+
+    // The owner only pays gas, the withdrawn funds move inside the actor
+    let spent = gas_cost(&messages[0]).add(gas_cost(&messages[1]));
+    assert_eq!(lotus.chain().lock().unwrap().balance(owner.address), TokenAmount::from_whole(100).sub(spent));
+}
+
+#[tokio::test]
+async fn msig_propose_then_approve_applies() {
+    let lotus = MockLotus::start().await.unwrap();
+    let proposer = funded_account(&lotus).await;
+    let approver = funded_account(&lotus).await;
+    let msig = Address::new_id(1500);
+    let inner = msig::InnerMessage {
+        to: Address::new_id(1501),
+        value: TokenAmount::from_whole(5),
+        method: 0,
+        params: RawBytes::default(),
+    };
+
+    lotus.script(
+        msig,
+        msig::METHOD_PROPOSE,
+        MockReceipt::default().with_return_dec(json!({"TxnID": 7, "Applied": false, "Code": 0})),
+    );
+    let ret =
+        msig::propose(lotus.endpoint().unwrap(), proposer.address, proposer.key_info.clone(), msig, inner.clone())
+            .await
+            .unwrap();
+    assert_eq!((ret.txn_id, ret.applied), (7, false));
+
+    let txn = MsigTransaction {
+        id: 7,
+        to: inner.to,
+        value: inner.value.clone(),
+        method: inner.method,
+        params: None,
+        approved: vec![proposer.address],
+    };
+    lotus.set_msig_pending(msig, vec![txn.clone()]);
+    lotus.script(
+        msig,
+        msig::METHOD_APPROVE,
+        MockReceipt::default().with_return_dec(json!({"Applied": true, "Code": 0})),
+    );
+    let ret =
+        msig::approve(lotus.endpoint().unwrap(), approver.address, approver.key_info.clone(), msig, 7).await.unwrap();
+    assert!(ret.applied && ret.code.is_success());
+
+    let messages = lotus.messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(
+        (messages[0].message.from, messages[0].message.to, messages[0].message.method_num),
+        (proposer.address, msig, msig::METHOD_PROPOSE)
+    );
+    assert_eq!(
+        (messages[1].message.from, messages[1].message.to, messages[1].message.method_num),
+        (approver.address, msig, msig::METHOD_APPROVE)
+    );
+    // The approval carries the transaction id and the hash of the pending proposal
+    let (txn_id, hash) = RawBytes::deserialize::<(i64, RawBytes)>(&messages[1].message.params).unwrap();
+    assert_eq!((txn_id, hash.bytes().to_vec()), (7, msig::proposal_hash(&txn).unwrap()));
+}
+
+#[tokio::test]
+async fn failing_withdraw_is_not_pushed_and_reports_the_simulated_trace() {
+    let lotus = MockLotus::start().await.unwrap();
+    let owner = funded_account(&lotus).await;
+    let actor_id = Address::new_id(1400);
+    lotus.script(actor_id, 19, MockReceipt::default().with_exit_code(ExitCode::USR_FORBIDDEN));
+
+    let res = actor::withdraw_miner(
+        lotus.endpoint().unwrap(),
+        owner.address,
+        owner.key_info.clone(),
+        actor_id,
+        Address::new_id(1401),
+        TokenAmount::from_whole(5),
+    )
+    .await;
+    match res {
+        Err(ActorError::MsgTraceError(exit_code, trace)) => {
+            assert_eq!(exit_code, ExitCode::USR_FORBIDDEN);
+            assert_eq!((trace.msg.to, trace.msg.method), (actor_id.into(), 19));
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(lotus.messages().is_empty());
+    assert_eq!(lotus.chain().lock().unwrap().balance(owner.address), TokenAmount::from_whole(100));
+}
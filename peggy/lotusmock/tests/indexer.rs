@@ -0,0 +1,90 @@
+use fvm_shared::{address::Address, crypto::signature::SignatureType, econ::TokenAmount};
+use indexer::Indexer;
+use lotusapi::LotusApi;
+use lotusmock::MockApi;
+use state::TipsetRef;
+
+struct Account {
+    address: Address,
+    key_info: forest_key_management::KeyInfo,
+}
+
+fn funded_account(api: &MockApi) -> Account {
+    let (address, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    api.chain().lock().unwrap().set_balance(address, TokenAmount::from_whole(100));
+    Account { address, key_info: key.key_info }
+}
+
+// Every mock push executes in a new tipset, returns its height
+async fn send(api: &MockApi, from: &Account, to: Address) -> i64 {
+    mpool::mpool_push(api.clone(), from.address, from.key_info.clone(), to, 0, TokenAmount::from_atto(1), ())
+        .await
+        .unwrap();
+    api.chain().lock().unwrap().height()
+}
+
+#[tokio::test]
+async fn backfill_indexes_messages_since_the_cursor() {
+    let api = MockApi::new();
+    let sender = funded_account(&api);
+    let robust = Address::new_actor(b"indexer-backfill");
+    let watched = Address::new_id(8000);
+    api.chain().lock().unwrap().register_actor(robust, watched);
+
+    let mut indexer = Indexer::open(api.clone(), ":memory:").unwrap();
+    assert_eq!(indexer.watch(watched).await.unwrap(), watched);
+
+    send(&api, &sender, watched).await;
+    let second = send(&api, &sender, robust).await;
+    send(&api, &sender, Address::new_id(8001)).await;
+    let third = send(&api, &sender, watched).await;
+
+    assert_eq!(indexer.backfill(second).await.unwrap(), 2);
+    let heights = indexer.messages(watched, 10).unwrap().iter().map(|msg| msg.height).collect::<Vec<_>>();
+    assert_eq!(heights, vec![third, second]);
+}
+
+#[tokio::test]
+async fn tipset_indexes_the_messages_it_executes() {
+    let api = MockApi::new();
+    let sender = funded_account(&api);
+    let watched = Address::new_id(8100);
+
+    let mut indexer = Indexer::open(api.clone(), ":memory:").unwrap();
+    indexer.watch(watched).await.unwrap();
+
+    let height = send(&api, &sender, watched).await;
+    let parent = TipsetRef::from(&api.chain_get_tipset_by_height(height - 1).await.unwrap());
+    let head = TipsetRef::from(&api.chain_head().await.unwrap());
+    assert_eq!(head.height, height);
+
+    assert_eq!(indexer.index_tipset(&parent).await.unwrap(), 0);
+    assert_eq!(indexer.index_tipset(&head).await.unwrap(), 1);
+
+    let msgs = indexer.messages(watched, 10).unwrap();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!((msgs[0].height, msgs[0].from, msgs[0].to), (height, sender.address, watched));
+}
+
+#[tokio::test]
+async fn revert_deletes_the_tipset_and_rolls_the_cursor_back() {
+    let api = MockApi::new();
+    let sender = funded_account(&api);
+    let watched = Address::new_id(8200);
+
+    let mut indexer = Indexer::open(api.clone(), ":memory:").unwrap();
+    indexer.watch(watched).await.unwrap();
+
+    let first = send(&api, &sender, watched).await;
+    let kept = TipsetRef::from(&api.chain_head().await.unwrap());
+    send(&api, &sender, watched).await;
+    let reverted = TipsetRef::from(&api.chain_head().await.unwrap());
+    indexer.index_tipset(&kept).await.unwrap();
+    indexer.index_tipset(&reverted).await.unwrap();
+    assert_eq!(indexer.messages(watched, 10).unwrap().len(), 2);
+
+    assert_eq!(indexer.revert_tipset(&reverted).unwrap(), 1);
+    let msgs = indexer.messages(watched, 10).unwrap();
+    assert_eq!(msgs.iter().map(|msg| msg.height).collect::<Vec<_>>(), vec![first]);
+    assert_eq!(indexer.cursor().unwrap(), Some(reverted.height - 1));
+}
@@ -0,0 +1,151 @@
+use fvm_ipld_encoding::Cbor;
+use fvm_shared::{address::Address, crypto::signature::SignatureType, econ::TokenAmount};
+use lotusmock::MockApi;
+use mpool::{
+    export_signed,
+    export_unsigned,
+    import_signed,
+    import_unsigned,
+    mpool_build,
+    mpool_push_signed,
+    sign_offline,
+    wait_msg_bumped,
+    BumpPolicy,
+    MpoolError,
+};
+use state::{wait_msg, WaitOptions};
+use std::time::Duration;
+
+// Rounds of a few hundred milliseconds, the mock only executes held messages once released
+fn bump_policy() -> BumpPolicy {
+    BumpPolicy::default().epochs(1).epoch_duration(Duration::from_millis(200))
+}
+
+#[tokio::test]
+async fn stuck_message_is_bumped() {
+    let api = MockApi::new();
+    let (from, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    api.chain().lock().unwrap().set_balance(from, TokenAmount::from_whole(10));
+    api.chain().lock().unwrap().hold();
+
+    let cid = mpool::mpool_push(
+        api.clone(),
+        from,
+        key.key_info.clone(),
+        Address::new_id(1700),
+        0,
+        TokenAmount::from_whole(1),
+        (),
+    )
+    .await
+    .unwrap();
+    let stuck = api.chain().lock().unwrap().pending()[0].clone();
+
+    // Executes the mpool once the replacement took the place of the stuck message
+    let release = async {
+        loop {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let chain = api.chain();
+            let mut chain = chain.lock().unwrap();
+            if chain.pending().iter().any(|msg| msg.gas_premium != stuck.gas_premium) {
+                chain.release();
+                break;
+            }
+        }
+    };
+    // A deadline makes the wait poll, a blocking StateWaitMsg of the mock fails right away on a pending message
+    let opts =
+        WaitOptions::default().confidence(0).timeout(Duration::from_secs(30)).poll_interval(Duration::from_millis(20));
+    let (res, _) = tokio::join!(
+        wait_msg_bumped::<_, ()>(api.clone(), key.key_info, cid.clone(), opts, bump_policy().max_bumps(1)),
+        release
+    );
+    res.unwrap();
+
+    let executed = api.chain().lock().unwrap().messages();
+    assert_eq!(executed.len(), 1);
+    let bumped = &executed[0].message;
+    assert_ne!(executed[0].cid, cid.0);
+    assert_eq!(bumped.sequence, stuck.sequence);
+    // Lotus refuses a replacement below 1.25x the pending premium
+    assert!(bumped.gas_premium.atto() >= &(stuck.gas_premium.atto() * 5u64 / 4u64 + 1u64));
+    assert!(bumped.gas_fee_cap >= bumped.gas_premium);
+}
+
+#[tokio::test]
+async fn message_executed_between_rounds_is_not_bumped() {
+    let api = MockApi::new();
+    let (from, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    api.chain().lock().unwrap().set_balance(from, TokenAmount::from_whole(10));
+    api.chain().lock().unwrap().hold();
+
+    let cid = mpool::mpool_push(
+        api.clone(),
+        from,
+        key.key_info.clone(),
+        Address::new_id(1701),
+        0,
+        TokenAmount::from_whole(1),
+        (),
+    )
+    .await
+    .unwrap();
+
+    // The round searches once and then sleeps past its end, the message lands while it sleeps
+    let release = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        api.chain().lock().unwrap().release();
+    };
+    let opts =
+        WaitOptions::default().confidence(0).timeout(Duration::from_secs(30)).poll_interval(Duration::from_secs(10));
+    let (res, _) =
+        tokio::join!(wait_msg_bumped::<_, ()>(api.clone(), key.key_info, cid.clone(), opts, bump_policy()), release);
+    res.unwrap();
+
+    let chain = api.chain();
+    let chain = chain.lock().unwrap();
+    assert_eq!(chain.messages().iter().map(|msg| msg.cid).collect::<Vec<_>>(), vec![cid.0]);
+    assert_eq!(chain.pushes(), 1);
+}
+
+#[tokio::test]
+async fn offline_signed_message_round_trips() {
+    let api = MockApi::new();
+    let (from, _, key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    api.chain().lock().unwrap().set_balance(from, TokenAmount::from_whole(10));
+    let to = Address::new_id(1800);
+
+    // Online machine exports the unsigned message, the air-gapped one signs it, the online one pushes it
+    let msg = mpool_build(api.clone(), from, to, 0, TokenAmount::from_whole(1), ()).await.unwrap();
+    let unsigned = std::env::temp_dir().join(format!("lotusmock-unsigned-{}.json", std::process::id()));
+    export_unsigned(&msg, &unsigned).unwrap();
+    let imported = import_unsigned(&unsigned).unwrap();
+    std::fs::remove_file(unsigned).unwrap();
+    assert_eq!(imported, msg);
+
+    let smsg = sign_offline(imported, &key.key_info).unwrap();
+    let signed = std::env::temp_dir().join(format!("lotusmock-signed-{}.json", std::process::id()));
+    export_signed(&smsg, &signed).unwrap();
+    let imported = import_signed(&signed).unwrap();
+    std::fs::remove_file(signed).unwrap();
+    assert_eq!(imported.cid().unwrap(), smsg.cid().unwrap());
+
+    let cid = mpool_push_signed(api.clone(), imported).await.unwrap();
+    wait_msg::<_, ()>(api.clone(), cid.clone()).await.unwrap();
+    assert_eq!(cid.0, smsg.cid().unwrap());
+    assert_eq!(api.chain().lock().unwrap().balance(to), TokenAmount::from_whole(1));
+}
+
+#[tokio::test]
+async fn sign_offline_rejects_another_key() {
+    let api = MockApi::new();
+    let (from, _, _, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    let (other, _, other_key, _) = wallet::create_wallet(SignatureType::Secp256k1);
+    api.chain().lock().unwrap().set_balance(from, TokenAmount::from_whole(10));
+
+    let msg = mpool_build(api.clone(), from, Address::new_id(1801), 0, TokenAmount::from_whole(1), ()).await.unwrap();
+    assert!(matches!(
+        sign_offline(msg, &other_key.key_info),
+        Err(MpoolError::SignerMismatch(msg_from, key_address)) if msg_from == from && key_address == other
+    ));
+}
@@ -0,0 +1,65 @@
+use fvm_shared::address::Address;
+use lotusapi::{AddressResolver, AnyAddress, FINALITY};
+use lotusmock::MockApi;
+
+#[tokio::test]
+async fn finalized_id_is_cached() {
+    let api = MockApi::new();
+    let robust = Address::new_actor(b"resolver-finalized");
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(6000));
+    api.chain().lock().unwrap().advance(FINALITY + 10);
+
+    let resolver = AddressResolver::new(api.clone());
+    assert_eq!(resolver.id(&AnyAddress::from(robust)).await.unwrap(), Address::new_id(6000));
+
+    // Served from the cache, the node is not asked again
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(6001));
+    assert_eq!(resolver.id(&AnyAddress::from(robust)).await.unwrap(), Address::new_id(6000));
+}
+
+#[tokio::test]
+async fn id_within_finality_is_not_cached() {
+    let api = MockApi::new();
+    let robust = Address::new_actor(b"resolver-young");
+    api.chain().lock().unwrap().advance(FINALITY + 10);
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(7000));
+
+    let resolver = AddressResolver::new(api.clone());
+    assert_eq!(resolver.id(&AnyAddress::from(robust)).await.unwrap(), Address::new_id(7000));
+
+    // A reorg may assign another id to the same robust address
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(7001));
+    assert_eq!(resolver.id(&AnyAddress::from(robust)).await.unwrap(), Address::new_id(7001));
+}
+
+#[tokio::test]
+async fn finalized_robust_is_cached() {
+    let api = MockApi::new();
+    let robust = Address::new_actor(b"resolver-robust-finalized");
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(7100));
+    api.chain().lock().unwrap().advance(FINALITY + 10);
+
+    let resolver = AddressResolver::new(api.clone());
+    assert_eq!(resolver.robust(&AnyAddress::from(robust)).await.unwrap(), AnyAddress::from(robust));
+
+    api.chain().lock().unwrap().register_actor(Address::new_actor(b"resolver-robust-other"), Address::new_id(7100));
+    let id = AnyAddress::from(Address::new_id(7100));
+    assert_eq!(resolver.robust(&id).await.unwrap(), AnyAddress::from(robust));
+}
+
+#[tokio::test]
+async fn robust_within_finality_is_not_cached() {
+    let api = MockApi::new();
+    let robust = Address::new_actor(b"resolver-robust-young");
+    api.chain().lock().unwrap().advance(FINALITY + 10);
+    api.chain().lock().unwrap().register_actor(robust, Address::new_id(7200));
+
+    let resolver = AddressResolver::new(api.clone());
+    assert_eq!(resolver.robust(&AnyAddress::from(robust)).await.unwrap(), AnyAddress::from(robust));
+
+    // After a reorg id 7200 belongs to another actor
+    let other = Address::new_actor(b"resolver-robust-reorged");
+    api.chain().lock().unwrap().register_actor(other, Address::new_id(7200));
+    let id = AnyAddress::from(Address::new_id(7200));
+    assert_eq!(resolver.robust(&id).await.unwrap(), AnyAddress::from(other));
+}
@@ -1,19 +1,20 @@
-use fil_actor_power::CreateMinerParams;
+use fil_actor_power::{CreateMinerParams, CreateMinerReturn as CreateMinerReturn1};
 use fil_actors_runtime::STORAGE_POWER_ACTOR_ADDR;
-use forest_json::cid::CidJson;
 use forest_key_management::KeyInfo;
 use fvm_ipld_encoding::BytesDe;
-use fvm_shared::{address::Address, econ::TokenAmount, sector::RegisteredPoStProof};
+use fvm_shared::{address::Address, econ::TokenAmount, message::Message, sector::RegisteredPoStProof};
 use libp2p::PeerId;
+use lotusapi::json::address;
 use multiaddr::Multiaddr;
 use rpc::RpcEndpoint;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use std::str::FromStr;
 use thiserror::Error;
 
-use mpool::{mpool_push, MpoolError};
-use state::{wait_msg, StateError};
+use mpool::{mpool_build, mpool_dry_run, mpool_push, wait_msg_bumped, BumpPolicy, MpoolError, Simulation};
+use msig::{propose, InnerMessage, MsigError, ProposeReturn};
+use state::{decode_cbor, decode_json, wait_msg, ReturnDecode, StateError, WaitOptions};
 
 #[derive(Error, Debug)]
 pub enum MinerError {
@@ -31,11 +32,16 @@ pub enum MinerError {
     StateCallError(#[from] StateError),
     #[error("parse multiaddr error: {0}")]
     ParseMultiaddrError(#[from] multiaddr::Error),
+    #[error("msig call error: {0}")]
+    MsigCallError(#[from] MsigError),
 }
 
-#[derive(Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
 pub struct CreateMinerReturn {
+    #[serde(rename = "IDAddress", with = "address")]
     pub id_address: Address,
+    #[serde(with = "address")]
     pub robust_address: Address,
 }
 
@@ -43,52 +49,54 @@ impl FromStr for CreateMinerReturn {
     type Err = MinerError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        #[derive(Deserialize)]
-        #[serde(rename_all = "PascalCase")]
-        struct T {
-            #[serde(rename = "IDAddress")]
-            id_address: String,
-            robust_address: String,
-        }
-
-        let v = serde_json::from_str::<T>(s)?;
+        Ok(serde_json::from_str::<Self>(s)?)
+    }
+}
 
-        let id_address = Address::from_str(&v.id_address)?;
-        let robust_address = Address::from_str(&v.robust_address)?;
+impl ReturnDecode for CreateMinerReturn {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        decode_json(ret)
+    }
 
-        Ok(Self { id_address, robust_address })
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        let ret = decode_cbor::<CreateMinerReturn1>(ret)?;
+        Ok(Self {
+            id_address: Address::from_str(&ret.id_address.to_string())?,
+            robust_address: Address::from_str(&ret.robust_address.to_string())?,
+        })
     }
 }
 
-pub async fn create_miner(
-    rpc: RpcEndpoint,
+fn create_miner_params(
     owner: Address,
-    owner_key_info: KeyInfo,
     worker: Address,
     window_post_proof_type: RegisteredPoStProof,
     peer_id: PeerId,
-) -> Result<(Address, Address), MinerError> {
+) -> Result<CreateMinerParams, MinerError> {
     let addr: Multiaddr = "/ip4/127.0.0.1/tcp/2345/http".parse()?;
-    let params = CreateMinerParams {
+    Ok(CreateMinerParams {
         owner,
         worker,
         window_post_proof_type,
         peer: peer_id.to_bytes(),
         multiaddrs: vec![BytesDe(addr.to_vec())],
-    };
+    })
+}
 
-    match mpool_push::<_, CidJson>(
-        rpc.clone(),
-        owner,
-        owner_key_info,
-        STORAGE_POWER_ACTOR_ADDR,
-        2,
-        TokenAmount::from_atto(0),
-        params,
-    )
-    .await
+pub async fn create_miner(
+    rpc: RpcEndpoint,
+    owner: Address,
+    owner_key_info: KeyInfo,
+    worker: Address,
+    window_post_proof_type: RegisteredPoStProof,
+    peer_id: PeerId,
+) -> Result<(Address, Address), MinerError> {
+    let params = create_miner_params(owner, worker, window_post_proof_type, peer_id)?;
+
+    match mpool_push(rpc.clone(), owner, owner_key_info, STORAGE_POWER_ACTOR_ADDR, 2, TokenAmount::from_atto(0), params)
+        .await
     {
-        Ok(res) => match wait_msg::<CreateMinerReturn>(rpc.clone(), res.clone()).await {
+        Ok(res) => match wait_msg::<_, CreateMinerReturn>(rpc.clone(), res.clone()).await {
             Ok(ret) => Ok((ret.id_address, ret.robust_address)),
             Err(err) => Err(MinerError::StateCallError(err)),
         },
@@ -96,28 +104,78 @@ pub async fn create_miner(
     }
 }
 
+pub async fn simulate_create_miner(
+    rpc: RpcEndpoint,
+    owner: Address,
+    worker: Address,
+    window_post_proof_type: RegisteredPoStProof,
+    peer_id: PeerId,
+) -> Result<Simulation, MinerError> {
+    let params = create_miner_params(owner, worker, window_post_proof_type, peer_id)?;
+
+    match mpool_dry_run(rpc, owner, STORAGE_POWER_ACTOR_ADDR, 2, TokenAmount::from_atto(0), params).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(MinerError::MpoolCallError(err)),
+    }
+}
+
 pub async fn change_owner(
     rpc: RpcEndpoint,
     owner: Address,
     owner_key_info: KeyInfo,
     miner_id: Address,
     new_owner_id: Address,
+    wait_options: WaitOptions,
 ) -> Result<(), MinerError> {
-    match mpool_push::<_, CidJson>(
-        rpc.clone(),
-        owner,
-        owner_key_info,
-        miner_id,
-        23,
-        TokenAmount::from_atto(0),
-        new_owner_id,
-    )
-    .await
+    match mpool_push(rpc.clone(), owner, owner_key_info.clone(), miner_id, 23, TokenAmount::from_atto(0), new_owner_id)
+        .await
     {
-        Ok(res) => match wait_msg::<serde_json::Value>(rpc, res).await {
-            Ok(_) => Ok(()),
-            Err(err) => Err(MinerError::StateCallError(err)),
-        },
+        Ok(res) => {
+            match wait_msg_bumped::<_, ()>(rpc, owner_key_info, res, wait_options, BumpPolicy::default()).await {
+                Ok(_) => Ok(()),
+                Err(err) => Err(MinerError::MpoolCallError(err)),
+            }
+        }
+        Err(err) => Err(MinerError::MpoolCallError(err)),
+    }
+}
+
+pub async fn build_change_owner(
+    rpc: RpcEndpoint,
+    owner: Address,
+    miner_id: Address,
+    new_owner_id: Address,
+) -> Result<Message, MinerError> {
+    match mpool_build(rpc, owner, miner_id, 23, TokenAmount::from_atto(0), new_owner_id).await {
+        Ok(msg) => Ok(msg),
         Err(err) => Err(MinerError::MpoolCallError(err)),
     }
 }
+
+pub async fn simulate_change_owner(
+    rpc: RpcEndpoint,
+    owner: Address,
+    miner_id: Address,
+    new_owner_id: Address,
+) -> Result<Simulation, MinerError> {
+    match mpool_dry_run(rpc, owner, miner_id, 23, TokenAmount::from_atto(0), new_owner_id).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(MinerError::MpoolCallError(err)),
+    }
+}
+
+// A miner owned by a multisig changes owner once the signers approve the proposal
+pub async fn propose_change_owner(
+    rpc: RpcEndpoint,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    miner_id: Address,
+    new_owner_id: Address,
+) -> Result<ProposeReturn, MinerError> {
+    let inner = InnerMessage::new(miner_id, TokenAmount::from_atto(0), 23, new_owner_id)?;
+    match propose(rpc, signer, signer_key_info, msig, inner).await {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(MinerError::MsigCallError(err)),
+    }
+}
@@ -1,13 +1,14 @@
-use forest_json::signed_message::json::SignedMessageJson;
+use forest_json::{cid::CidJson, signed_message::json::SignedMessageJson};
 use forest_key_management::KeyInfo;
 use forest_message::signed_message::SignedMessage;
-use forest_rpc_api::mpool_api;
 use fvm_ipld_encoding::{Cbor, RawBytes};
 use fvm_shared::{address::Address, econ::TokenAmount, message::Message};
 use gasestimator::{estimate_msg_gas, GasEstimatorError};
-use log::error;
+use log::{error, warn};
+use lotusapi::{is_address_of_network, AddressResolver, AnyAddress, LotusApi};
 use num_bigint::BigInt;
-use rpc::{RpcEndpoint, RpcError};
+use rpc::{LotusError, RpcEndpoint, RpcError, Subscription};
+use serde::Deserialize;
 use std::{
     cmp::Ordering,
     ops::{Add, Mul},
@@ -15,6 +16,34 @@ use std::{
 use thiserror::Error;
 use wallet::{get_balance, WalletError};
 
+mod nonce;
+mod offline;
+mod replace;
+mod simulate;
+
+pub use nonce::NonceReservation;
+pub use offline::{export_signed, export_unsigned, import_signed, import_unsigned, sign_offline, MessagePreview};
+pub use replace::{mpool_replace, wait_msg_bumped, BumpPolicy, EPOCH_DURATION};
+pub use simulate::{mpool_dry_run, mpool_simulate, Simulation};
+
+const MPOOL_SUB: &str = "Filecoin.MpoolSub";
+
+const MPOOL_UPDATE_ADD: u8 = 0;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MpoolUpdate {
+    #[serde(rename = "Type")]
+    pub update_type: u8,
+    pub message: SignedMessageJson,
+}
+
+impl MpoolUpdate {
+    pub fn is_add(&self) -> bool {
+        self.update_type == MPOOL_UPDATE_ADD
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum MpoolError {
     #[error("rpc request error: {0}")]
@@ -31,42 +60,116 @@ pub enum MpoolError {
     WalletCallError(#[from] WalletError),
     #[error("insufficient funds")]
     InsufficientFunds,
+    #[error("unknown address {0}")]
+    UnknownAddress(AnyAddress),
+    #[error("state call error: {0}")]
+    StateCallError(#[from] state::StateError),
+    #[error("io call error: {0}")]
+    IOCallError(#[from] std::io::Error),
+    #[error("parse json error: {0}")]
+    ParseJsonError(#[from] serde_json::Error),
+    #[error("nonce {0} already used, node nonce {1}")]
+    StaleNonce(u64, u64),
+    #[error("message is from {0}, key is of {1}")]
+    SignerMismatch(Address, Address),
+    #[error("network mismatch: expect {0}, node is on {1}")]
+    NetworkMismatch(String, String),
+    #[error("address {0} does not belong to network {1}")]
+    AddressNetworkMismatch(Address, String),
+    #[error("simulated message fails with {}\n{}", .0.exit_code, .0.trace)]
+    SimulationFailed(Box<Simulation>),
 }
 
-async fn mpool_get_nonce(rpc: RpcEndpoint, address: Address) -> Result<u64, MpoolError> {
-    match rpc.post::<_, u64>(mpool_api::MPOOL_GET_NONCE, vec![address.to_string()]).await {
+async fn mpool_get_nonce<A: LotusApi>(api: A, address: Address) -> Result<u64, MpoolError> {
+    match api.mpool_get_nonce(address).await {
         Ok(res) => Ok(res),
         Err(err) => Err(MpoolError::RpcRequestError(err)),
     }
 }
 
-pub async fn mpool_push<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
-    rpc: RpcEndpoint,
+pub async fn mpool_sub(rpc: RpcEndpoint) -> Result<Subscription<MpoolUpdate>, MpoolError> {
+    match rpc.subscribe::<_, MpoolUpdate>(MPOOL_SUB, Vec::<String>::new()).await {
+        Ok(sub) => Ok(sub),
+        Err(err) => Err(MpoolError::RpcRequestError(err)),
+    }
+}
+
+// A runner prepared for one network must never sign against a node of another one
+async fn check_network<A: LotusApi>(api: A, addrs: &[Address]) -> Result<(), MpoolError> {
+    let expected = match api.expected_network() {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    let network = api.state_network_name().await?;
+    if network != expected {
+        error!("Node network {} does not match {}", network, expected);
+        return Err(MpoolError::NetworkMismatch(expected, network));
+    }
+
+    match addrs.iter().find(|addr| !is_address_of_network(addr, &network)) {
+        Some(addr) => Err(MpoolError::AddressNetworkMismatch(*addr, network)),
+        None => Ok(()),
+    }
+}
+
+// Messages are built for the id address, so f4 and eth destinations fit in a fvm_shared 2 message
+async fn resolve_to<A: LotusApi>(api: A, to: AnyAddress) -> Result<Address, MpoolError> {
+    match AddressResolver::new(api).id(&to).await {
+        Ok(id) => Ok(id),
+        Err(RpcError::LotusError(LotusError::ActorNotFound(_))) => match to {
+            // Sending to a robust address not on chain yet creates the account
+            AnyAddress::Filecoin(addr) => Ok(addr),
+            to => Err(MpoolError::UnknownAddress(to)),
+        },
+        Err(err) => Err(MpoolError::RpcRequestError(err)),
+    }
+}
+
+async fn prepare_to<A: LotusApi>(api: A, from: Address, to: AnyAddress) -> Result<Address, MpoolError> {
+    if let AnyAddress::Filecoin(to) = to {
+        check_network(api.clone(), &[from, to]).await?;
+    } else {
+        check_network(api.clone(), &[from]).await?;
+    }
+    resolve_to(api, to).await
+}
+
+fn new_message<T: serde::Serialize>(
     from: Address,
-    from_key_info: KeyInfo,
     to: Address,
     method_num: u64,
     value: TokenAmount,
-    params: T1,
-) -> Result<T2, MpoolError> {
-    let nonce = mpool_get_nonce(rpc.clone(), from).await?;
-    let balance = get_balance(rpc.clone(), from).await?;
-
-    let params = RawBytes::serialize(params)?;
-    let msg = Message {
+    params: T,
+    nonce: u64,
+) -> Result<Message, MpoolError> {
+    Ok(Message {
         version: 0,
         to,
         from,
         method_num,
-        value: value.clone(),
+        value,
         sequence: nonce,
-        params,
+        params: RawBytes::serialize(params)?,
         gas_fee_cap: TokenAmount::from_atto(0),
         gas_limit: 0,
         gas_premium: TokenAmount::from_atto(0),
-    };
+    })
+}
+
+async fn build_message<A: LotusApi, T: serde::Serialize>(
+    api: A,
+    from: Address,
+    to: Address,
+    method_num: u64,
+    value: TokenAmount,
+    params: T,
+    nonce: u64,
+) -> Result<Message, MpoolError> {
+    let balance = get_balance(api.clone(), from).await?;
 
-    let msg = estimate_msg_gas(rpc.clone(), msg.clone()).await?;
+    let msg = new_message(from, to, method_num, value.clone(), params, nonce)?;
+    let msg = estimate_msg_gas(api.clone(), msg.clone()).await?;
 
     let gas_fee = msg.clone().gas_fee_cap.add(msg.clone().gas_premium.mul(BigInt::from(msg.clone().gas_limit)));
     if balance.cmp(&gas_fee.clone().add(value.clone())) == Ordering::Less {
@@ -74,16 +177,114 @@ pub async fn mpool_push<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'d
         return Err(MpoolError::InsufficientFunds);
     }
 
+    Ok(msg)
+}
+
+// Every message runs through StateCall before it is signed, a failing one is never paid for
+async fn check_simulation<A: LotusApi>(api: A, msg: Message) -> Result<(), MpoolError> {
+    let simulation = mpool_simulate(api, msg).await?;
+    match simulation.is_success() {
+        true => Ok(()),
+        false => Err(MpoolError::SimulationFailed(Box::new(simulation))),
+    }
+}
+
+// Nonce and gas are filled from the node but nothing is signed, the message can leave for an offline signer
+pub async fn mpool_build<A: LotusApi, T: serde::Serialize>(
+    api: A,
+    from: Address,
+    to: impl Into<AnyAddress>,
+    method_num: u64,
+    value: TokenAmount,
+    params: T,
+) -> Result<Message, MpoolError> {
+    let to = prepare_to(api.clone(), from, to.into()).await?;
+    let nonce = mpool_get_nonce(api.clone(), from).await?;
+    build_message(api, from, to, method_num, value, params, nonce).await
+}
+
+pub async fn mpool_push<A: LotusApi, T: serde::Serialize>(
+    api: A,
+    from: Address,
+    from_key_info: KeyInfo,
+    to: impl Into<AnyAddress>,
+    method_num: u64,
+    value: TokenAmount,
+    params: T,
+) -> Result<CidJson, MpoolError> {
+    let to = prepare_to(api.clone(), from, to.into()).await?;
+    // Concurrent pushes from one account share the node nonce, the reservation hands each a distinct one
+    let mut reservation = NonceReservation::reserve(from, mpool_get_nonce(api.clone(), from).await?);
+    let mut msg = build_message(api.clone(), from, to, method_num, value, params, reservation.nonce()).await?;
+    check_simulation(api.clone(), msg.clone()).await?;
+
+    let mut resynced = false;
+    loop {
+        let smsg = sign_message(msg.clone(), &from_key_info)?;
+        let cid = smsg.cid()?;
+
+        match api.mpool_push(smsg).await {
+            Ok(res) => {
+                reservation.commit();
+                return Ok(CidJson(res));
+            }
+            Err(RpcError::LotusError(err)) if err.is_already_in_mpool() => {
+                warn!("Account {} message {} already in mpool", from, cid);
+                reservation.commit();
+                return Ok(CidJson(cid));
+            }
+            Err(RpcError::LotusError(err)) if err.is_nonce_conflict() && !resynced => {
+                let nonce = mpool_get_nonce(api.clone(), from).await?;
+                if nonce == msg.sequence {
+                    return Err(MpoolError::RpcRequestError(RpcError::LotusError(err)));
+                }
+                reservation = NonceReservation::reserve(from, nonce);
+                warn!("Account {} nonce {} rejected: {}, resync to {}", from, msg.sequence, err, reservation.nonce());
+                msg.sequence = reservation.nonce();
+                resynced = true;
+            }
+            Err(RpcError::LotusError(LotusError::InsufficientFunds(err))) => {
+                error!("Account {} insufficient funds: {}", from, err);
+                return Err(MpoolError::InsufficientFunds);
+            }
+            Err(err) => return Err(MpoolError::RpcRequestError(err)),
+        }
+    }
+}
+
+// A message signed elsewhere is pushed as is, its nonce and gas were fixed when it was built
+pub async fn mpool_push_signed<A: LotusApi>(api: A, smsg: SignedMessage) -> Result<CidJson, MpoolError> {
+    let (from, sequence) = (smsg.message().from, smsg.message().sequence);
+    check_network(api.clone(), &[from, smsg.message().to]).await?;
+
+    let nonce = mpool_get_nonce(api.clone(), from).await?;
+    if nonce > sequence {
+        error!("Account {} nonce {} already used, node nonce {}", from, sequence, nonce);
+        return Err(MpoolError::StaleNonce(sequence, nonce));
+    }
+    check_simulation(api.clone(), smsg.message().clone()).await?;
+
+    let cid = smsg.cid()?;
+    match api.mpool_push(smsg).await {
+        Ok(res) => Ok(CidJson(res)),
+        Err(RpcError::LotusError(err)) if err.is_already_in_mpool() => {
+            warn!("Account {} message {} already in mpool", from, cid);
+            Ok(CidJson(cid))
+        }
+        Err(RpcError::LotusError(LotusError::InsufficientFunds(err))) => {
+            error!("Account {} insufficient funds: {}", from, err);
+            Err(MpoolError::InsufficientFunds)
+        }
+        Err(err) => Err(MpoolError::RpcRequestError(err)),
+    }
+}
+
+pub fn sign_message(msg: Message, from_key_info: &KeyInfo) -> Result<SignedMessage, MpoolError> {
     let msg_cid = msg.cid()?;
     let sig = forest_key_management::sign(
         *from_key_info.key_type(),
         from_key_info.private_key(),
         msg_cid.to_bytes().as_slice(),
     )?;
-    let smsg = SignedMessage::new_from_parts(msg, sig)?;
-
-    match rpc.post::<_, T2>(mpool_api::MPOOL_PUSH, vec![SignedMessageJson(smsg.clone())]).await {
-        Ok(res) => Ok(res),
-        Err(err) => Err(MpoolError::RpcRequestError(err)),
-    }
+    Ok(SignedMessage::new_from_parts(msg, sig)?)
 }
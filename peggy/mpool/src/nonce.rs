@@ -0,0 +1,193 @@
+use fvm_shared::address::Address;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+static NONCES: Mutex<BTreeMap<String, AccountNonces>> = Mutex::new(BTreeMap::new());
+
+#[derive(Default)]
+struct AccountNonces {
+    next: u64,
+    // Reserved but not pushed yet
+    in_flight: BTreeSet<u64>,
+    // Given back by failed pushes, reused before next so the account never keeps a gap
+    released: BTreeSet<u64>,
+    // One past the highest pushed nonce, kept until the node nonce catches up with it
+    committed: u64,
+}
+
+impl AccountNonces {
+    fn reserve(&mut self, node_nonce: u64) -> u64 {
+        // The node nonce may be fetched before our last push reached it
+        if node_nonce >= self.committed {
+            self.committed = 0;
+        }
+        let floor = node_nonce.max(self.committed);
+
+        // Nothing of ours is pending, so the node knows better than we do
+        if self.in_flight.is_empty() {
+            self.next = floor;
+        } else {
+            self.next = self.next.max(floor);
+        }
+        let next = self.next;
+        self.released.retain(|nonce| *nonce >= node_nonce && *nonce < next);
+
+        let nonce = match self.released.iter().next().cloned() {
+            Some(nonce) => {
+                self.released.remove(&nonce);
+                nonce
+            }
+            None => {
+                self.next += 1;
+                self.next - 1
+            }
+        };
+        self.in_flight.insert(nonce);
+        nonce
+    }
+
+    fn commit(&mut self, nonce: u64) {
+        self.in_flight.remove(&nonce);
+        self.committed = self.committed.max(nonce + 1);
+    }
+
+    fn release(&mut self, nonce: u64) {
+        self.in_flight.remove(&nonce);
+        if nonce + 1 != self.next {
+            self.released.insert(nonce);
+            return;
+        }
+
+        self.next = nonce;
+        while self.next > 0 && self.released.remove(&(self.next - 1)) {
+            self.next -= 1;
+        }
+    }
+}
+
+// Dropped without commit when the push fails, the nonce goes back to the account
+pub struct NonceReservation {
+    from: Address,
+    nonce: u64,
+    committed: bool,
+}
+
+impl NonceReservation {
+    pub fn reserve(from: Address, node_nonce: u64) -> Self {
+        let nonce = NONCES.lock().unwrap().entry(from.to_string()).or_default().reserve(node_nonce);
+        Self { from, nonce, committed: false }
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn commit(mut self) {
+        if let Some(nonces) = NONCES.lock().unwrap().get_mut(&self.from.to_string()) {
+            nonces.commit(self.nonce);
+        }
+        self.committed = true;
+    }
+}
+
+impl Drop for NonceReservation {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        if let Some(nonces) = NONCES.lock().unwrap().get_mut(&self.from.to_string()) {
+            nonces.release(self.nonce);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reservations are process wide, every test works on its own account
+    fn account(id: u64) -> Address {
+        Address::new_id(id)
+    }
+
+    #[test]
+    fn concurrent_reservations_get_consecutive_nonces() {
+        let from = account(100);
+        let first = NonceReservation::reserve(from, 5);
+        let second = NonceReservation::reserve(from, 5);
+        assert_eq!((first.nonce(), second.nonce()), (5, 6));
+    }
+
+    #[test]
+    fn dropped_reservation_gives_the_nonce_back() {
+        let from = account(101);
+        let first = NonceReservation::reserve(from, 5);
+        let second = NonceReservation::reserve(from, 5);
+        drop(second);
+
+        let third = NonceReservation::reserve(from, 5);
+        assert_eq!((first.nonce(), third.nonce()), (5, 6));
+    }
+
+    #[test]
+    fn released_gap_is_filled_before_next() {
+        let from = account(102);
+        let first = NonceReservation::reserve(from, 5);
+        let second = NonceReservation::reserve(from, 5);
+        drop(first);
+
+        let third = NonceReservation::reserve(from, 5);
+        let fourth = NonceReservation::reserve(from, 5);
+        assert_eq!((second.nonce(), third.nonce(), fourth.nonce()), (6, 5, 7));
+    }
+
+    #[test]
+    fn committed_nonce_is_not_reused() {
+        let from = account(103);
+        let first = NonceReservation::reserve(from, 5);
+        let second = NonceReservation::reserve(from, 5);
+        first.commit();
+
+        // The node has not seen the pushed message yet
+        let third = NonceReservation::reserve(from, 5);
+        assert_eq!((second.nonce(), third.nonce()), (6, 7));
+    }
+
+    #[test]
+    fn node_nonce_fetched_before_commit_does_not_collide() {
+        let from = account(104);
+        // Both pushes read node nonce 5, the first one is pushed before the second reserves
+        NonceReservation::reserve(from, 5).commit();
+
+        let second = NonceReservation::reserve(from, 5);
+        assert_eq!(second.nonce(), 6);
+        second.commit();
+        assert_eq!(NonceReservation::reserve(from, 6).nonce(), 7);
+    }
+
+    #[test]
+    fn node_nonce_wins_once_it_passes_committed() {
+        let from = account(106);
+        NonceReservation::reserve(from, 5).commit();
+        NonceReservation::reserve(from, 6).commit();
+
+        // The node has not seen the pushed messages yet, the floor holds
+        assert_eq!(NonceReservation::reserve(from, 5).nonce(), 7);
+        // Another wallet pushed with the same key meanwhile
+        assert_eq!(NonceReservation::reserve(from, 9).nonce(), 9);
+    }
+
+    #[test]
+    fn released_nonces_below_node_nonce_are_dropped() {
+        let from = account(105);
+        let first = NonceReservation::reserve(from, 5);
+        let second = NonceReservation::reserve(from, 5);
+        drop(first);
+
+        // Another wallet used nonce 5 meanwhile
+        let third = NonceReservation::reserve(from, 6);
+        assert_eq!((second.nonce(), third.nonce()), (6, 7));
+    }
+}
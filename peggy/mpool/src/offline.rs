@@ -0,0 +1,70 @@
+use forest_json::{message::json::MessageJson, signed_message::json::SignedMessageJson};
+use forest_key_management::{Key, KeyInfo};
+use forest_message::signed_message::SignedMessage;
+use fvm_ipld_encoding::Cbor;
+use fvm_shared::message::Message;
+use num_bigint::BigInt;
+use state::ReturnDecode;
+use std::{fmt, path::Path};
+
+use crate::{sign_message, MpoolError};
+
+pub fn export_unsigned(msg: &Message, path: impl AsRef<Path>) -> Result<(), MpoolError> {
+    std::fs::write(path, serde_json::to_string_pretty(&MessageJson(msg.clone()))?)?;
+    Ok(())
+}
+
+pub fn import_unsigned(path: impl AsRef<Path>) -> Result<Message, MpoolError> {
+    let MessageJson(msg) = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(msg)
+}
+
+pub fn export_signed(smsg: &SignedMessage, path: impl AsRef<Path>) -> Result<(), MpoolError> {
+    std::fs::write(path, serde_json::to_string_pretty(&SignedMessageJson(smsg.clone()))?)?;
+    Ok(())
+}
+
+pub fn import_signed(path: impl AsRef<Path>) -> Result<SignedMessage, MpoolError> {
+    let SignedMessageJson(smsg) = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(smsg)
+}
+
+// Runs on the air-gapped machine, nothing but the key and the message file is needed
+pub fn sign_offline(msg: Message, key_info: &KeyInfo) -> Result<SignedMessage, MpoolError> {
+    let key = Key::try_from(key_info.clone())?;
+    if key.address != msg.from {
+        return Err(MpoolError::SignerMismatch(msg.from, key.address));
+    }
+    sign_message(msg, key_info)
+}
+
+// Shown before every stage so the signer sees what the params decode to, not just a blob
+pub struct MessagePreview<'a>(pub &'a Message);
+
+impl fmt::Display for MessagePreview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = self.0;
+        match msg.cid() {
+            Ok(cid) => writeln!(f, "Message {}", cid)?,
+            Err(_) => writeln!(f, "Message")?,
+        }
+        writeln!(f, "  From:        {}", msg.from)?;
+        writeln!(f, "  To:          {}", msg.to)?;
+        writeln!(f, "  Method:      {}", msg.method_num)?;
+        writeln!(f, "  Value:       {}", msg.value)?;
+        writeln!(f, "  Nonce:       {}", msg.sequence)?;
+        writeln!(f, "  Gas Limit:   {}", msg.gas_limit)?;
+        writeln!(f, "  Gas Fee Cap: {}", msg.gas_fee_cap)?;
+        writeln!(f, "  Gas Premium: {}", msg.gas_premium)?;
+        writeln!(f, "  Max Fee:     {}", msg.gas_fee_cap.clone() * BigInt::from(msg.gas_limit))?;
+
+        let params = match msg.params.bytes() {
+            [] => serde_json::Value::Null,
+            bytes => match <serde_json::Value as ReturnDecode>::decode_cbor(bytes) {
+                Ok(params) => params,
+                Err(_) => serde_json::Value::String(hex::encode(bytes)),
+            },
+        };
+        write!(f, "  Params:      {}", serde_json::to_string_pretty(&params).unwrap_or_default())
+    }
+}
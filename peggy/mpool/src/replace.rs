@@ -0,0 +1,136 @@
+use forest_json::cid::CidJson;
+use forest_key_management::KeyInfo;
+use fvm_shared::{econ::TokenAmount, message::Message};
+use gasestimator::estimate_msg_gas;
+use log::warn;
+use lotusapi::LotusApi;
+use rpc::{LotusError, RpcError};
+use state::{wait_msg_lookup, wait_msg_with, ReturnDecode, StateError, WaitOptions};
+use std::time::{Duration, Instant};
+
+use crate::{check_network, sign_message, MpoolError};
+
+pub const EPOCH_DURATION: Duration = Duration::from_secs(30);
+
+// Lotus only accepts a replacement paying at least 1.25x the premium of the pending message
+const REPLACE_BY_FEE_RATIO_NUM: u64 = 5;
+const REPLACE_BY_FEE_RATIO_DEN: u64 = 4;
+
+#[derive(Clone, Debug)]
+pub struct BumpPolicy {
+    pub epochs: i64,
+    pub max_bumps: u32,
+    // Devnets and tests produce blocks faster than mainnet
+    pub epoch_duration: Duration,
+}
+
+impl Default for BumpPolicy {
+    fn default() -> Self {
+        Self { epochs: 10, max_bumps: 3, epoch_duration: EPOCH_DURATION }
+    }
+}
+
+impl BumpPolicy {
+    pub fn disabled() -> Self {
+        Self { epochs: 0, max_bumps: 0, ..Default::default() }
+    }
+
+    pub fn epochs(mut self, epochs: i64) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    pub fn max_bumps(mut self, max_bumps: u32) -> Self {
+        self.max_bumps = max_bumps;
+        self
+    }
+
+    pub fn epoch_duration(mut self, epoch_duration: Duration) -> Self {
+        self.epoch_duration = epoch_duration;
+        self
+    }
+}
+
+fn min_rbf_premium(premium: &TokenAmount) -> TokenAmount {
+    TokenAmount::from_atto(premium.atto() * REPLACE_BY_FEE_RATIO_NUM / REPLACE_BY_FEE_RATIO_DEN + 1u64)
+}
+
+fn max_amount(a: TokenAmount, b: TokenAmount) -> TokenAmount {
+    match a < b {
+        true => b,
+        false => a,
+    }
+}
+
+// Same nonce and payload, only the premium and the fee cap are raised so the node swaps the pending message
+pub async fn mpool_replace<A: LotusApi>(api: A, from_key_info: KeyInfo, cid: CidJson) -> Result<CidJson, MpoolError> {
+    let CidJson(cid) = cid;
+    let msg = api.chain_get_message(cid).await?;
+    check_network(api.clone(), &[msg.from, msg.to]).await?;
+
+    let estimate =
+        Message { gas_fee_cap: TokenAmount::from_atto(0), gas_premium: TokenAmount::from_atto(0), ..msg.clone() };
+    let estimate = estimate_msg_gas(api.clone(), estimate).await?;
+
+    let gas_premium = max_amount(min_rbf_premium(&msg.gas_premium), estimate.gas_premium);
+    let gas_fee_cap = max_amount(max_amount(msg.gas_fee_cap.clone(), estimate.gas_fee_cap), gas_premium.clone());
+    warn!(
+        "Replace message {} nonce {}: premium {} -> {}, fee cap {} -> {}",
+        cid, msg.sequence, msg.gas_premium, gas_premium, msg.gas_fee_cap, gas_fee_cap
+    );
+
+    let msg = Message { gas_premium, gas_fee_cap, ..msg };
+    let smsg = sign_message(msg, &from_key_info)?;
+    match api.mpool_push(smsg).await {
+        Ok(res) => Ok(CidJson(res)),
+        Err(err) => Err(MpoolError::RpcRequestError(err)),
+    }
+}
+
+// Each round waits the policy epochs for inclusion, a message still not executed afterwards is replaced with a higher
+// fee. Confidence is only awaited once the message landed, a replacement of an executed message would be refused
+pub async fn wait_msg_bumped<A: LotusApi, T: ReturnDecode>(
+    api: A,
+    from_key_info: KeyInfo,
+    cid: CidJson,
+    opts: WaitOptions,
+    policy: BumpPolicy,
+) -> Result<T, MpoolError> {
+    let mut cid = cid;
+    let mut bumps = 0;
+    loop {
+        if bumps >= policy.max_bumps || policy.epochs <= 0 {
+            return Ok(wait_msg_with(api, cid, opts).await?);
+        }
+
+        let round = Instant::now() + policy.epoch_duration * policy.epochs as u32;
+        let deadline = match opts.deadline {
+            Some(deadline) if deadline <= round => deadline,
+            _ => round,
+        };
+        let round_opts = WaitOptions { confidence: 0, deadline: Some(deadline), ..opts.clone() };
+
+        match wait_msg_lookup(api.clone(), cid.clone(), &round_opts).await {
+            Ok(_) => return Ok(wait_msg_with(api, cid, opts).await?),
+            Err(StateError::WaitTimeout(pending)) if opts.deadline.map(|d| d > Instant::now()).unwrap_or(true) => {
+                // The message may have landed right as the round ended
+                if api.state_search_msg(cid.0, opts.lookback, opts.allow_replaced).await?.is_some() {
+                    return Ok(wait_msg_with(api, cid, opts).await?);
+                }
+                warn!("Message {} not included after {} epochs, bump fee", pending, policy.epochs);
+                match mpool_replace(api.clone(), from_key_info.clone(), cid.clone()).await {
+                    Ok(replaced) => {
+                        cid = replaced;
+                        bumps += 1;
+                    }
+                    // The pending message landed while the replacement was built
+                    Err(MpoolError::RpcRequestError(RpcError::LotusError(LotusError::NonceTooLow(_)))) => {
+                        bumps = policy.max_bumps;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            Err(err) => return Err(MpoolError::StateCallError(err)),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use forest_ipld::{json::IpldJson, Ipld};
+use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode, message::Message};
+use log::warn;
+use lotusapi::{AnyAddress, ExecutionTrace, LotusApi};
+use state::decode_return;
+use std::fmt;
+
+use crate::{mpool_get_nonce, new_message, prepare_to, MpoolError};
+
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub exit_code: ExitCode,
+    pub return_value: serde_json::Value,
+    pub gas_used: i64,
+    pub trace: ExecutionTrace,
+    pub error: String,
+}
+
+impl Simulation {
+    pub fn is_success(&self) -> bool {
+        self.exit_code.is_success()
+    }
+}
+
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  Exit Code:   {}", self.exit_code)?;
+        writeln!(f, "  Return:      {}", self.return_value)?;
+        write!(f, "  Gas Used:    {}", self.gas_used)?;
+        if !self.error.is_empty() {
+            write!(f, "\n  Error:       {}", self.error)?;
+        }
+        if let Some(failure) = self.trace.failure() {
+            write!(f, "\n  Failed Call: {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+// The message is executed on top of the head exactly as built, no signature and no gas paid
+pub async fn mpool_simulate<A: LotusApi>(api: A, msg: Message) -> Result<Simulation, MpoolError> {
+    let res = match api.state_call(msg).await {
+        Ok(res) => res,
+        Err(err) => return Err(MpoolError::RpcRequestError(err)),
+    };
+
+    let (exit_code, return_data, gas_used) = match res.msg_rct {
+        Some(receipt) => (receipt.exit_code, receipt.return_data, receipt.gas_used),
+        None => (res.execution_trace.msg_rct.exit_code, None, res.execution_trace.gas_used()),
+    };
+    let return_value = match exit_code.is_success() {
+        true => decode_return::<serde_json::Value>(IpldJson(Ipld::Null), return_data)?,
+        false => serde_json::Value::Null,
+    };
+
+    let simulation = Simulation { exit_code, return_value, gas_used, trace: res.execution_trace, error: res.error };
+    if !simulation.is_success() {
+        warn!("Simulated message {} fails with {}", res.msg_cid.0, simulation.exit_code);
+    }
+    Ok(simulation)
+}
+
+pub async fn mpool_dry_run<A: LotusApi, T: serde::Serialize>(
+    api: A,
+    from: Address,
+    to: impl Into<AnyAddress>,
+    method_num: u64,
+    value: TokenAmount,
+    params: T,
+) -> Result<Simulation, MpoolError> {
+    let to = prepare_to(api.clone(), from, to.into()).await?;
+    let nonce = mpool_get_nonce(api.clone(), from).await?;
+    // Gas is left to the node, estimation would already fail on the message we want the trace of
+    let msg = new_message(from, to, method_num, value, params, nonce)?;
+    mpool_simulate(api, msg).await
+}
@@ -0,0 +1,269 @@
+use forest_key_management::KeyInfo;
+use fvm_ipld_encoding::{
+    strict_bytes,
+    tuple::{Deserialize_tuple, Serialize_tuple},
+    Cbor,
+    RawBytes,
+};
+use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode};
+use log::info;
+use lotusapi::{LotusApi, MsigTransaction};
+use rpc::RpcError;
+use serde::Deserialize;
+use state::{decode_cbor, decode_json, wait_msg, ReturnDecode, StateError};
+use thiserror::Error;
+
+use mpool::{mpool_push, MpoolError};
+
+pub const METHOD_PROPOSE: u64 = 2;
+pub const METHOD_APPROVE: u64 = 3;
+pub const METHOD_CANCEL: u64 = 4;
+
+#[derive(Error, Debug)]
+pub enum MsigError {
+    #[error("mpool call error: {0}")]
+    MpoolCallError(#[from] MpoolError),
+    #[error("state call error: {0}")]
+    StateCallError(#[from] StateError),
+    #[error("rpc request error: {0}")]
+    RpcRequestError(#[from] RpcError),
+    #[error("fvm ipld encoding error: {0}")]
+    FvmIpldEncodingError(#[from] fvm_ipld_encoding::Error),
+    #[error("parse base64 error: {0}")]
+    ParseBase64Error(#[from] base64::DecodeError),
+    #[error("transaction {0} is not pending")]
+    TransactionNotFound(i64),
+    #[error("proposed message code error: {0}")]
+    InnerMsgCodeError(ExitCode),
+}
+
+// The message the multisig sends once enough signers approved it
+#[derive(Clone, Debug)]
+pub struct InnerMessage {
+    pub to: Address,
+    pub value: TokenAmount,
+    pub method: u64,
+    pub params: RawBytes,
+}
+
+impl InnerMessage {
+    pub fn new<T: serde::Serialize>(
+        to: Address,
+        value: TokenAmount,
+        method: u64,
+        params: T,
+    ) -> Result<Self, MsigError> {
+        Ok(Self { to, value, method, params: RawBytes::serialize(params)? })
+    }
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+struct ProposeParams {
+    to: Address,
+    value: TokenAmount,
+    method: u64,
+    params: RawBytes,
+}
+impl Cbor for ProposeParams {}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+struct TxnIdParams {
+    id: i64,
+    #[serde(with = "strict_bytes")]
+    proposal_hash: Vec<u8>,
+}
+impl Cbor for TxnIdParams {}
+
+#[derive(Serialize_tuple)]
+struct ProposalHashData {
+    requester: Option<Address>,
+    to: Address,
+    value: TokenAmount,
+    method: u64,
+    params: RawBytes,
+}
+impl Cbor for ProposalHashData {}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ProposeReturn {
+    pub txn_id: i64,
+    // A threshold of one executes the proposal right away
+    pub applied: bool,
+    pub code: ExitCode,
+    pub ret: RawBytes,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ApproveReturn {
+    pub applied: bool,
+    pub code: ExitCode,
+    pub ret: RawBytes,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ReturnDecJson {
+    #[serde(rename = "TxnID", default)]
+    txn_id: i64,
+    applied: bool,
+    code: u32,
+    #[serde(default)]
+    ret: Option<String>,
+}
+
+impl ReturnDecJson {
+    fn ret(&self) -> Result<RawBytes, StateError> {
+        match &self.ret {
+            Some(ret) => match base64::decode(ret) {
+                Ok(ret) => Ok(RawBytes::new(ret)),
+                Err(err) => Err(StateError::ConvertReturnDecError(err.to_string())),
+            },
+            None => Ok(RawBytes::default()),
+        }
+    }
+}
+
+impl ReturnDecode for ProposeReturn {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        let ret = decode_json::<ReturnDecJson>(ret)?;
+        Ok(Self { txn_id: ret.txn_id, applied: ret.applied, code: ExitCode::new(ret.code), ret: ret.ret()? })
+    }
+
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        decode_cbor::<ProposeReturn>(ret)
+    }
+}
+
+impl ReturnDecode for ApproveReturn {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        let ret = decode_json::<ReturnDecJson>(ret)?;
+        Ok(Self { applied: ret.applied, code: ExitCode::new(ret.code), ret: ret.ret()? })
+    }
+
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        decode_cbor::<ApproveReturn>(ret)
+    }
+}
+
+pub async fn pending<A: LotusApi>(api: A, msig: Address) -> Result<Vec<MsigTransaction>, MsigError> {
+    match api.msig_get_pending(msig).await {
+        Ok(txns) => Ok(txns),
+        Err(err) => Err(MsigError::RpcRequestError(err)),
+    }
+}
+
+// Binds an approval to the reviewed content, the actor refuses it if the pending transaction differs
+pub fn proposal_hash(txn: &MsigTransaction) -> Result<Vec<u8>, MsigError> {
+    let params = match &txn.params {
+        Some(params) => RawBytes::new(base64::decode(params)?),
+        None => RawBytes::default(),
+    };
+    let data = ProposalHashData {
+        requester: txn.approved.first().cloned(),
+        to: txn.to,
+        value: txn.value.clone(),
+        method: txn.method,
+        params,
+    };
+    let hash = blake2b_simd::Params::new().hash_length(32).to_state().update(&data.marshal_cbor()?).finalize();
+    Ok(hash.as_bytes().to_vec())
+}
+
+pub async fn propose<A: LotusApi>(
+    api: A,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    inner: InnerMessage,
+) -> Result<ProposeReturn, MsigError> {
+    let params = ProposeParams { to: inner.to, value: inner.value, method: inner.method, params: inner.params };
+    let cid = mpool_push(api.clone(), signer, signer_key_info, msig, METHOD_PROPOSE, TokenAmount::from_atto(0), params)
+        .await?;
+
+    let ret = wait_msg::<_, ProposeReturn>(api, cid).await?;
+    if ret.applied && !ret.code.is_success() {
+        return Err(MsigError::InnerMsgCodeError(ret.code));
+    }
+    info!("> Multisig {} transaction {} proposed, applied {}", msig, ret.txn_id, ret.applied);
+    Ok(ret)
+}
+
+async fn pending_txn<A: LotusApi>(api: A, msig: Address, txn_id: i64) -> Result<MsigTransaction, MsigError> {
+    match pending(api, msig).await?.into_iter().find(|txn| txn.id == txn_id) {
+        Some(txn) => Ok(txn),
+        None => Err(MsigError::TransactionNotFound(txn_id)),
+    }
+}
+
+pub async fn approve<A: LotusApi>(
+    api: A,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    txn_id: i64,
+) -> Result<ApproveReturn, MsigError> {
+    let txn = pending_txn(api.clone(), msig, txn_id).await?;
+    let params = TxnIdParams { id: txn_id, proposal_hash: proposal_hash(&txn)? };
+    let cid = mpool_push(api.clone(), signer, signer_key_info, msig, METHOD_APPROVE, TokenAmount::from_atto(0), params)
+        .await?;
+
+    let ret = wait_msg::<_, ApproveReturn>(api, cid).await?;
+    if ret.applied && !ret.code.is_success() {
+        return Err(MsigError::InnerMsgCodeError(ret.code));
+    }
+    info!("> Multisig {} transaction {} approved, applied {}", msig, txn_id, ret.applied);
+    Ok(ret)
+}
+
+// Only the proposer may cancel
+pub async fn cancel<A: LotusApi>(
+    api: A,
+    signer: Address,
+    signer_key_info: KeyInfo,
+    msig: Address,
+    txn_id: i64,
+) -> Result<(), MsigError> {
+    let txn = pending_txn(api.clone(), msig, txn_id).await?;
+    let params = TxnIdParams { id: txn_id, proposal_hash: proposal_hash(&txn)? };
+    let cid = mpool_push(api.clone(), signer, signer_key_info, msig, METHOD_CANCEL, TokenAmount::from_atto(0), params)
+        .await?;
+
+    wait_msg::<_, ()>(api, cid).await?;
+    info!("> Multisig {} transaction {} cancelled", msig, txn_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txn(approved: Vec<Address>, value: TokenAmount, params: Option<&str>) -> MsigTransaction {
+        MsigTransaction {
+            id: 0,
+            to: Address::new_id(102),
+            value,
+            method: 2,
+            params: params.map(str::to_string),
+            approved,
+        }
+    }
+
+    // Expected hashes are blake2b-256 of the ProposalHashData cbor builtin-actors computes in compute_proposal_hash
+    #[test]
+    fn proposal_hash_matches_the_actor() {
+        let txn = txn(vec![Address::new_id(101), Address::new_id(103)], TokenAmount::from_whole(1), Some("ggEC"));
+        assert_eq!(
+            hex::encode(proposal_hash(&txn).unwrap()),
+            "55bc05af0734bc757ce4a66c8c36a59cd7cfe562f6ac8773921dafea7e755dcd"
+        );
+    }
+
+    #[test]
+    fn proposal_hash_without_requester() {
+        let txn = txn(Vec::new(), TokenAmount::from_atto(0), None);
+        assert_eq!(
+            hex::encode(proposal_hash(&txn).unwrap()),
+            "f11f1b2dc47e34a7aa56cb75fa8ba073367900e55fd5b42da6a9000681ef31e5"
+        );
+    }
+}
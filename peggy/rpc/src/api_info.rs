@@ -0,0 +1,136 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::RpcError;
+
+const FULLNODE_API_INFO: &str = "FULLNODE_API_INFO";
+const LOTUS_PATH: &str = "LOTUS_PATH";
+const DEFAULT_LOTUS_PATH: &str = ".lotus";
+const RPC_PATH: &str = "/rpc/v0";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiInfo {
+    pub url: String,
+    pub token: String,
+}
+
+impl FromStr for ApiInfo {
+    type Err = RpcError;
+
+    // Accept token:/ip4/127.0.0.1/tcp/1234/http, a bare multiaddr or a plain url
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.contains("://") {
+            return Ok(Self { url: s.to_string(), token: String::default() });
+        }
+
+        // Like lotus, the prefix is a token only when the rest is a multiaddr, an ip6 multiaddr has colons itself
+        if let Some((token, addr)) = s.split_once(':') {
+            if let Ok(url) = multiaddr_to_url(addr) {
+                return Ok(Self { url, token: token.to_string() });
+            }
+        }
+
+        Ok(Self { url: multiaddr_to_url(s)?, token: String::default() })
+    }
+}
+
+fn multiaddr_to_url(addr: &str) -> Result<String, RpcError> {
+    let mut host = None;
+    let mut port = None;
+    let mut scheme = "http";
+
+    let mut protocols = addr.trim_start_matches('/').split('/');
+    while let Some(protocol) = protocols.next() {
+        match protocol {
+            "ip4" | "dns" | "dns4" | "dns6" => host = protocols.next().map(|host| host.to_string()),
+            "ip6" => host = protocols.next().map(|host| format!("[{}]", host)),
+            "tcp" => port = protocols.next(),
+            "http" | "https" | "ws" | "wss" => scheme = protocol,
+            _ => return Err(RpcError::InvalidApiInfo(addr.to_string())),
+        }
+    }
+
+    match (host, port) {
+        (Some(host), Some(port)) => Ok(format!("{}://{}:{}{}", scheme, host, port, RPC_PATH)),
+        _ => Err(RpcError::InvalidApiInfo(addr.to_string())),
+    }
+}
+
+impl ApiInfo {
+    pub fn read_token<P: AsRef<Path>>(path: P) -> Result<String, RpcError> {
+        Ok(std::fs::read_to_string(path)?.trim().to_string())
+    }
+
+    pub fn from_repo<P: AsRef<Path>>(repo: P) -> Result<Self, RpcError> {
+        let addr = std::fs::read_to_string(repo.as_ref().join("api"))?;
+        Ok(Self { url: multiaddr_to_url(addr.trim())?, token: Self::read_token(repo.as_ref().join("token"))? })
+    }
+
+    pub fn lotus_path() -> Option<PathBuf> {
+        match env::var(LOTUS_PATH) {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => env::var("HOME").ok().map(|home| PathBuf::from(home).join(DEFAULT_LOTUS_PATH)),
+        }
+    }
+
+    // FULLNODE_API_INFO wins over the lotus repo, comma separated infos are used for failover
+    pub fn from_env() -> Result<Vec<Self>, RpcError> {
+        if let Ok(infos) = env::var(FULLNODE_API_INFO) {
+            return infos.split(',').filter(|info| !info.trim().is_empty()).map(Self::from_str).collect();
+        }
+
+        match Self::lotus_path() {
+            Some(path) => Ok(vec![Self::from_repo(path)?]),
+            None => Err(RpcError::NoEndpoint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::ApiInfo;
+
+    #[test]
+    fn parses_token_and_multiaddr() {
+        let info = ApiInfo::from_str("eyJhbGciOiJIUzI1NiJ9.e30.sig:/ip4/127.0.0.1/tcp/1234/http").unwrap();
+        assert_eq!(info.url, "http://127.0.0.1:1234/rpc/v0");
+        assert_eq!(info.token, "eyJhbGciOiJIUzI1NiJ9.e30.sig");
+    }
+
+    #[test]
+    fn parses_tokenless_multiaddr() {
+        let info = ApiInfo::from_str("/dns/lotus.example/tcp/443/wss").unwrap();
+        assert_eq!(info.url, "wss://lotus.example:443/rpc/v0");
+        assert!(info.token.is_empty());
+    }
+
+    #[test]
+    fn parses_ip6_multiaddr_with_and_without_token() {
+        let info = ApiInfo::from_str("/ip6/::1/tcp/1234/http").unwrap();
+        assert_eq!(info.url, "http://[::1]:1234/rpc/v0");
+        assert!(info.token.is_empty());
+
+        let info = ApiInfo::from_str("token:/ip6/::1/tcp/1234/http").unwrap();
+        assert_eq!(info.url, "http://[::1]:1234/rpc/v0");
+        assert_eq!(info.token, "token");
+    }
+
+    #[test]
+    fn keeps_plain_url() {
+        let info = ApiInfo::from_str(" http://localhost:1234/rpc/v1 ").unwrap();
+        assert_eq!(info.url, "http://localhost:1234/rpc/v1");
+        assert!(info.token.is_empty());
+    }
+
+    #[test]
+    fn rejects_incomplete_multiaddr() {
+        assert!(ApiInfo::from_str("token:/ip4/127.0.0.1/http").is_err());
+        assert!(ApiInfo::from_str("/unix/lotus.sock").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use crate::RpcError;
+
+pub struct BatchCall<T> {
+    index: usize,
+    _marker: PhantomData<T>,
+}
+
+#[derive(Default)]
+pub struct Batch {
+    pub(crate) calls: Vec<(String, serde_json::Value)>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn call<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
+        &mut self,
+        method: &str,
+        params: T1,
+    ) -> BatchCall<T2> {
+        self.calls.push((method.to_string(), serde_json::json!(params)));
+        BatchCall { index: self.calls.len() - 1, _marker: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+}
+
+pub struct BatchResults {
+    results: Vec<Option<Result<serde_json::Value, RpcError>>>,
+    debug: bool,
+}
+
+impl BatchResults {
+    pub(crate) fn new(
+        ids: &[i64],
+        mut responses: HashMap<i64, Result<serde_json::Value, RpcError>>,
+        debug: bool,
+    ) -> Self {
+        let results = ids.iter().map(|id| Some(responses.remove(id).unwrap_or(Err(RpcError::Unknown)))).collect();
+        Self { results, debug }
+    }
+
+    pub fn take<T: for<'de> serde::Deserialize<'de>>(&mut self, call: BatchCall<T>) -> Result<T, RpcError> {
+        match self.results.get_mut(call.index).and_then(|res| res.take()) {
+            Some(Ok(res)) => crate::parse_response(res, self.debug),
+            Some(Err(err)) => Err(err),
+            None => Err(RpcError::Unknown),
+        }
+    }
+}
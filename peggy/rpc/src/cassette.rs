@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::RpcError;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Interaction {
+    pub method: String,
+    pub params: serde_json::Value,
+    pub response: serde_json::Value,
+}
+
+enum Mode {
+    Record(File),
+    Replay(usize),
+}
+
+// Cassettes already started by this process, an endpoint rebuilt for the same session keeps appending to them
+static RECORDING: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+pub(crate) struct Cassette {
+    mode: Mutex<Mode>,
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    // One interaction per line, the file is truncated by the first recorder of the process only
+    pub(crate) fn record(path: &Path) -> Result<Self, RpcError> {
+        let truncate = RECORDING.lock().unwrap().insert(path.to_path_buf());
+        let file = match truncate {
+            true => File::create(path)?,
+            false => OpenOptions::new().append(true).create(true).open(path)?,
+        };
+        Ok(Self { mode: Mutex::new(Mode::Record(file)), interactions: Vec::new() })
+    }
+
+    // Cassettes recorded as a single json array are still accepted
+    pub(crate) fn replay(path: &Path) -> Result<Self, RpcError> {
+        let content = std::fs::read_to_string(path)?;
+        let interactions = match content.trim_start().starts_with('[') {
+            true => serde_json::from_str::<Vec<Interaction>>(&content)?,
+            false => content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str::<Interaction>)
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        Ok(Self { mode: Mutex::new(Mode::Replay(0)), interactions })
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(*self.mode.lock().unwrap(), Mode::Replay(_))
+    }
+
+    pub(crate) fn save(&self, method: &str, req: &serde_json::Value, res: &serde_json::Value) -> Result<(), RpcError> {
+        let mut mode = self.mode.lock().unwrap();
+        let file = match &mut *mode {
+            Mode::Record(file) => file,
+            Mode::Replay(_) => return Ok(()),
+        };
+
+        let mut response = serde_json::Map::new();
+        for key in ["result", "error"] {
+            if let Some(value) = res.get(key) {
+                response.insert(key.to_string(), value.clone());
+            }
+        }
+
+        let interaction = Interaction {
+            method: method.to_string(),
+            params: req.get("params").cloned().unwrap_or(serde_json::Value::Null),
+            response: serde_json::Value::Object(response),
+        };
+        writeln!(file, "{}", serde_json::to_string(&interaction)?)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn play(&self, method: &str, req: &serde_json::Value) -> Result<serde_json::Value, RpcError> {
+        let mut mode = self.mode.lock().unwrap();
+        let cursor = match &mut *mode {
+            Mode::Replay(cursor) => cursor,
+            Mode::Record(_) => return Err(RpcError::CassetteMismatch(format!("{} is not replayable", method))),
+        };
+
+        let interaction = match self.interactions.get(*cursor) {
+            Some(interaction) => interaction,
+            None => return Err(RpcError::CassetteExhausted(method.to_string())),
+        };
+
+        let params = req.get("params").cloned().unwrap_or(serde_json::Value::Null);
+        if interaction.method != method || interaction.params != params {
+            return Err(RpcError::CassetteMismatch(format!(
+                "#{} expect {} {} but got {} {}",
+                cursor, interaction.method, interaction.params, method, params
+            )));
+        }
+
+        *cursor += 1;
+        Ok(interaction.response.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Cassette;
+    use crate::{Batch, RetryPolicy, RpcEndpoint, RpcError};
+
+    fn cassette_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("peggy-cassette-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    fn request(method: &str, params: serde_json::Value) -> serde_json::Value {
+        json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1000})
+    }
+
+    #[test]
+    fn record_appends_one_interaction_per_line() {
+        let path = cassette_path("append");
+        let cassette = Cassette::record(&path).unwrap();
+        for height in 0..3 {
+            cassette
+                .save(
+                    "Filecoin.ChainHead",
+                    &request("Filecoin.ChainHead", json!([])),
+                    &json!({"jsonrpc": "2.0", "id": 1000, "result": {"Height": height}}),
+                )
+                .unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn recording_again_in_the_same_process_appends() {
+        let path = cassette_path("reopen");
+        for height in 0..2 {
+            Cassette::record(&path)
+                .unwrap()
+                .save(
+                    "Filecoin.ChainHead",
+                    &request("Filecoin.ChainHead", json!([])),
+                    &json!({"jsonrpc": "2.0", "id": 1000, "result": {"Height": height}}),
+                )
+                .unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_batch_is_recorded_and_replayed() {
+        let path = cassette_path("failure");
+        // Nothing listens on port 1, the batch fails at transport level
+        let rpc = RpcEndpoint::new("http://127.0.0.1:1/rpc/v0", "")
+            .unwrap()
+            .retry_policy(RetryPolicy::never())
+            .record(&path)
+            .unwrap();
+        let mut batch = Batch::new();
+        batch.call::<_, u64>("Filecoin.MpoolGetNonce", json!(["t01000"]));
+        assert!(rpc.post_batch(batch).await.is_err());
+
+        let rpc = RpcEndpoint::new("http://127.0.0.1:1/rpc/v0", "").unwrap().replay(&path).unwrap();
+        let mut batch = Batch::new();
+        let nonce = batch.call::<_, u64>("Filecoin.MpoolGetNonce", json!(["t01000"]));
+        let mut results = rpc.post_batch(batch).await.unwrap();
+        let err = results.take(nonce).unwrap_err();
+        assert!(err.to_string().contains("low level error"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_returns_recorded_responses_in_order() {
+        let path = cassette_path("replay");
+        let cassette = Cassette::record(&path).unwrap();
+        cassette
+            .save(
+                "Filecoin.MpoolGetNonce",
+                &request("Filecoin.MpoolGetNonce", json!(["t01000"])),
+                &json!({"jsonrpc": "2.0", "id": 1000, "result": 7}),
+            )
+            .unwrap();
+        cassette
+            .save(
+                "Filecoin.StateNetworkName",
+                &request("Filecoin.StateNetworkName", json!([])),
+                &json!({"jsonrpc": "2.0", "id": 1001, "result": "calibrationnet"}),
+            )
+            .unwrap();
+        drop(cassette);
+
+        // The url is never dialed while replaying
+        let rpc = RpcEndpoint::new("http://127.0.0.1:1/rpc/v0", "").unwrap().replay(&path).unwrap();
+        assert_eq!(rpc.post::<_, u64>("Filecoin.MpoolGetNonce", json!(["t01000"])).await.unwrap(), 7);
+        assert_eq!(rpc.post::<_, String>("Filecoin.StateNetworkName", json!([])).await.unwrap(), "calibrationnet");
+        assert!(matches!(
+            rpc.post::<_, String>("Filecoin.StateNetworkName", json!([])).await,
+            Err(RpcError::CassetteExhausted(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_rejects_diverging_request() {
+        let path = cassette_path("mismatch");
+        let cassette = Cassette::record(&path).unwrap();
+        cassette
+            .save(
+                "Filecoin.MpoolGetNonce",
+                &request("Filecoin.MpoolGetNonce", json!(["t01000"])),
+                &json!({"jsonrpc": "2.0", "id": 1000, "result": 7}),
+            )
+            .unwrap();
+        drop(cassette);
+
+        let rpc = RpcEndpoint::new("http://127.0.0.1:1/rpc/v0", "").unwrap().replay(&path).unwrap();
+        assert!(matches!(
+            rpc.post::<_, u64>("Filecoin.MpoolGetNonce", json!(["t01001"])).await,
+            Err(RpcError::CassetteMismatch(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_accepts_json_array_cassette() {
+        let path = cassette_path("array");
+        std::fs::write(
+            &path,
+            json!([{"method": "Filecoin.ChainHead", "params": [], "response": {"result": {"Height": 1}}}]).to_string(),
+        )
+        .unwrap();
+
+        let cassette = Cassette::replay(&path).unwrap();
+        let res = cassette.play("Filecoin.ChainHead", &request("Filecoin.ChainHead", json!([]))).unwrap();
+        assert_eq!(res, json!({"result": {"Height": 1}}));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,46 +1,68 @@
 use jsonrpc_v2::RequestObject;
-use log::{error, info};
-use reqwest::{
-    header::{AUTHORIZATION, CONTENT_TYPE},
-    Client,
-};
+use log::{info, warn};
+use serde::Deserialize;
 use serde_json::json;
 use std::{
+    collections::HashMap,
+    future::Future,
+    path::Path,
     str::FromStr,
     sync::{
-        atomic::{
-            AtomicUsize,
-            Ordering::{Relaxed, SeqCst},
-        },
+        atomic::{AtomicUsize, Ordering::SeqCst},
         Arc,
     },
     time::Duration,
 };
 use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{field, info_span, Instrument, Span};
 use url::{ParseError, Url};
 
+mod api_info;
+mod batch;
+mod cassette;
+mod limiter;
+mod lotus;
+mod metrics;
+mod node;
+mod pool;
+mod version;
+mod ws;
+
+pub use api_info::ApiInfo;
+pub use batch::{Batch, BatchCall, BatchResults};
+use cassette::Cassette;
+pub use cassette::Interaction;
+use limiter::RateLimiter;
+pub use lotus::LotusError;
+use metrics::Metrics;
+pub use metrics::{MethodMetrics, LATENCY_BUCKETS_MS};
+use node::Node;
+use pool::NodePool;
+pub use pool::{is_idempotent, is_long_poll, is_retriable, RetryPolicy};
+pub use version::{NodeImpl, NodeInfo, STATE_NETWORK_NAME, VERSION};
+pub use ws::Subscription;
+
 const RPC_START_ID: usize = 1000;
+const CHAIN_HEAD: &str = "Filecoin.ChainHead";
 
 #[derive(Clone)]
 pub struct RpcEndpoint {
-    url: Url,
+    pool: Arc<NodePool>,
     request_id: Arc<AtomicUsize>,
-    bearer_token: String,
     debug: bool,
+    retry: RetryPolicy,
+    cassette: Option<Arc<Cassette>>,
+    metrics: Arc<Metrics>,
+    limiter: Option<Arc<RateLimiter>>,
+    network: Option<String>,
 }
 
 impl FromStr for RpcEndpoint {
-    type Err = ParseError;
+    type Err = RpcError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let url = Url::parse(s)?;
-
-        Ok(Self {
-            url,
-            request_id: Arc::new(AtomicUsize::new(RPC_START_ID)),
-            bearer_token: String::default(),
-            debug: false,
-        })
+        Self::new(s, "")
     }
 }
 
@@ -48,89 +70,669 @@ impl FromStr for RpcEndpoint {
 pub enum RpcError {
     #[error("low level error: {0}")]
     LowLevelError(#[from] reqwest::Error),
+    #[error("websocket error: {0}")]
+    WebSocketError(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("parse url error: {0}")]
+    ParseUrlError(#[from] ParseError),
     #[error("fail request")]
     RequestError,
     #[error("rpc application error {0}")]
     RpcApplicationError(serde_json::Value),
+    #[error("lotus error: {0}")]
+    LotusError(#[from] LotusError),
     #[error("rpc response parse error")]
     RpcResponseParseError,
     #[error("rpc application result parse error: {0}")]
     RpcApplicationResultParseError(#[from] serde_json::Error),
+    #[error("invalid bearer token")]
+    InvalidBearerToken,
+    #[error("connection closed")]
+    ConnectionClosed,
+    #[error("request timeout")]
+    Timeout,
+    #[error("subscription is only supported over websocket")]
+    SubscriptionUnsupported,
+    #[error("no rpc endpoint is configured")]
+    NoEndpoint,
+    #[error("no healthy rpc endpoint")]
+    NoHealthyEndpoint,
+    #[error("rpc endpoint serves network {1} instead of {0}")]
+    NetworkMismatch(String, String),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("invalid lotus api info: {0}")]
+    InvalidApiInfo(String),
+    #[error("cassette mismatch: {0}")]
+    CassetteMismatch(String),
+    #[error("cassette exhausted at {0}")]
+    CassetteExhausted(String),
     #[error("unknown error")]
     Unknown,
 }
 
+pub(crate) fn parse_response<T2: for<'de> serde::Deserialize<'de>>(
+    res: serde_json::Value,
+    debug: bool,
+) -> Result<T2, RpcError> {
+    if debug {
+        info!("Response: {}", res);
+    }
+
+    if res.get("result").is_some() {
+        match serde_json::from_value::<T2>(res.get("result").unwrap().clone()) {
+            Ok(res) => return Ok(res),
+            Err(err) => return Err(RpcError::RpcApplicationResultParseError(err)),
+        };
+    }
+
+    if res.get("error").is_some() {
+        return Err(RpcError::from_application_error(res.get("error").unwrap().clone()));
+    }
+
+    Err(RpcError::Unknown)
+}
+
+impl RpcError {
+    pub fn from_application_error(err: serde_json::Value) -> Self {
+        match LotusError::parse(&err) {
+            Some(err) => Self::LotusError(err),
+            None => Self::RpcApplicationError(err),
+        }
+    }
+
+    pub fn lotus_error(&self) -> Option<&LotusError> {
+        match self {
+            Self::LotusError(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::LowLevelError(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err.is_request()
+                    || err.status().map(|s| s.is_server_error() || s.as_u16() == 429).unwrap_or(false)
+            }
+            Self::WebSocketError(_) | Self::ConnectionClosed | Self::Timeout => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::LowLevelError(err) => err.is_timeout(),
+            Self::Timeout => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for RpcError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::WebSocketError(Box::new(err))
+    }
+}
+
 impl RpcEndpoint {
-    pub fn new(url: &str, bearer_token: &str) -> Result<Self, ParseError> {
-        let url = Url::parse(url)?;
+    pub fn new(url: &str, bearer_token: &str) -> Result<Self, RpcError> {
+        Self::new_pool(&[(url, bearer_token)])
+    }
+
+    pub fn new_pool(endpoints: &[(&str, &str)]) -> Result<Self, RpcError> {
+        if endpoints.is_empty() {
+            return Err(RpcError::NoEndpoint);
+        }
+
+        let mut nodes = Vec::new();
+        for (url, bearer_token) in endpoints {
+            nodes.push(Node::new(url, bearer_token)?);
+        }
 
         Ok(Self {
-            url,
+            pool: Arc::new(NodePool::new(nodes)),
             request_id: Arc::new(AtomicUsize::new(RPC_START_ID)),
-            bearer_token: bearer_token.to_string(),
             debug: false,
+            retry: RetryPolicy::default(),
+            cassette: None,
+            metrics: Arc::new(Metrics::default()),
+            limiter: None,
+            network: None,
         })
     }
 
+    pub fn from_api_info(infos: &[ApiInfo]) -> Result<Self, RpcError> {
+        let endpoints = infos.iter().map(|info| (info.url.as_str(), info.token.as_str())).collect::<Vec<_>>();
+        Self::new_pool(&endpoints)
+    }
+
+    pub fn from_env() -> Result<Self, RpcError> {
+        Self::from_api_info(&ApiInfo::from_env()?)
+    }
+
     pub fn debug(mut self) -> Self {
         self.debug = true;
         self
     }
 
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    // Network the caller signs for, messages are refused when the node reports another one
+    pub fn network(mut self, network: &str) -> Self {
+        self.network = Some(network.to_string());
+        self
+    }
+
+    pub fn expected_network(&self) -> Option<String> {
+        self.network.clone()
+    }
+
+    pub fn metrics(&self) -> HashMap<String, MethodMetrics> {
+        self.metrics.snapshot()
+    }
+
+    pub fn throttled(&self) -> Duration {
+        self.metrics.throttled()
+    }
+
+    pub fn record<P: AsRef<Path>>(mut self, path: P) -> Result<Self, RpcError> {
+        self.cassette = Some(Arc::new(Cassette::record(path.as_ref())?));
+        Ok(self)
+    }
+
+    // Replay never touches the network, the url given to the endpoint is only a placeholder
+    pub fn replay<P: AsRef<Path>>(mut self, path: P) -> Result<Self, RpcError> {
+        self.cassette = Some(Arc::new(Cassette::replay(path.as_ref())?));
+        Ok(self)
+    }
+
+    fn replaying(&self) -> Option<&Cassette> {
+        self.cassette.as_deref().filter(|cassette| cassette.is_replay())
+    }
+
+    fn save(&self, method: &str, req: &serde_json::Value, res: &serde_json::Value) -> Result<(), RpcError> {
+        match &self.cassette {
+            Some(cassette) => cassette.save(method, req, res),
+            None => Ok(()),
+        }
+    }
+
+    // A request that never got a response is recorded as an error, replay must stay in step with the session
+    fn save_failure(&self, method: &str, req: &serde_json::Value, err: &RpcError) -> Result<(), RpcError> {
+        self.save(method, req, &json!({"error": {"code": -32603, "message": err.to_string()}}))
+    }
+
+    pub fn url(&self) -> Url {
+        self.pool.active().url().clone()
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        self.pool.active().is_websocket()
+    }
+
+    fn request<T1: serde::Serialize>(&self, method: &str, params: T1) -> (i64, serde_json::Value) {
+        let id = self.request_id.fetch_add(1, SeqCst) as i64;
+        let req = RequestObject::request().with_params(json!(params)).with_method(method).with_id(id).finish();
+
+        if self.debug {
+            info!("Request: {:?}", req);
+        }
+
+        (id, json!(req))
+    }
+
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            let wait = limiter.acquire().await;
+            if !wait.is_zero() {
+                self.metrics.throttle(wait);
+            }
+        }
+    }
+
+    fn observe(&self, methods: &[&str], start: Instant, success: bool) {
+        let elapsed = start.elapsed();
+        Span::current().record("duration_ms", elapsed.as_millis() as u64);
+        for method in methods {
+            self.metrics.observe(method, elapsed, success);
+        }
+    }
+
+    async fn with_retry<R, F, Fut>(&self, method: &str, retriable: bool, f: F) -> Result<R, RpcError>
+    where
+        F: Fn(Arc<Node>) -> Fut,
+        Fut: Future<Output = Result<R, RpcError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let node = self.pool.active();
+            let res = match self.verify_network(&node).await {
+                Ok(()) => f(node.clone()).await,
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(res) => return Ok(res),
+                Err(RpcError::NetworkMismatch(expected, network)) => {
+                    warn!("{} serves network {} instead of {}, exclude it", node.url(), network, expected);
+                    if !self.pool.exclude(&node) {
+                        return Err(RpcError::NetworkMismatch(expected, network));
+                    }
+                }
+                Err(err) if retriable && err.is_transient() && attempt < self.retry.max_retries => {
+                    warn!(
+                        "{} -> {} FAIL: {}, retry {}/{}",
+                        node.url(),
+                        method,
+                        err,
+                        attempt + 1,
+                        self.retry.max_retries
+                    );
+                    self.pool.failover(&node);
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    if err.is_transient() && !(is_long_poll(method) && err.is_timeout()) {
+                        self.pool.failover(&node);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    // Every node is checked before its first request, a failover must not land on a node of another network
+    async fn verify_network(&self, node: &Node) -> Result<(), RpcError> {
+        let expected = match &self.network {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+
+        let network = match node.network() {
+            Some(network) => network,
+            None => {
+                let (id, req) = self.request(STATE_NETWORK_NAME, Vec::<String>::new());
+                let network = parse_response::<String>(node.send(STATE_NETWORK_NAME, id, &req).await?, self.debug)?;
+                node.set_network(&network);
+                network
+            }
+        };
+
+        match &network == expected {
+            true => Ok(()),
+            false => Err(RpcError::NetworkMismatch(expected.clone(), network)),
+        }
+    }
+
     pub async fn post<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
         &self,
         method: &str,
         params: T1,
     ) -> Result<T2, RpcError> {
-        let req = RequestObject::request()
-            .with_params(json!(params))
-            .with_method(method)
-            .with_id(self.request_id.load(Relaxed) as i64)
-            .finish();
+        let (id, req) = self.request(method, params);
 
-        if self.debug {
-            info!("Request: {:?}", req);
+        if let Some(cassette) = self.replaying() {
+            return parse_response(cassette.play(method, &req)?, self.debug);
         }
 
-        self.request_id.fetch_add(1, SeqCst);
+        let span = info_span!("rpc", method, id, duration_ms = field::Empty);
+        let res = async {
+            self.throttle().await;
 
-        let cli = Client::builder().timeout(Duration::from_secs(600)).build()?;
+            let start = Instant::now();
+            let res = self
+                .with_retry(method, is_retriable(method), |node| {
+                    let req = &req;
+                    async move { node.send(method, id, req).await }
+                })
+                .await;
 
-        let res = cli
-            .post(self.url.clone())
-            .header(CONTENT_TYPE, "application/json")
-            .header(AUTHORIZATION, format!("Bearer {}", self.bearer_token))
-            .json(&req)
-            .send()
-            .await?;
+            self.observe(&[method], start, res.as_ref().map(|res| res.get("error").is_none()).unwrap_or(false));
+            res
+        }
+        .instrument(span)
+        .await;
 
-        let res = match res.error_for_status() {
-            Ok(res) => {
-                info!("POST -> {} SUCCESS", method);
-                res
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                self.save_failure(method, &req, &err)?;
+                return Err(err);
             }
+        };
+        self.save(method, &req, &res)?;
+
+        parse_response(res, self.debug)
+    }
+
+    // Answers that describe the node itself must come from that node, there is no retry and no failover in between
+    async fn post_node<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
+        &self,
+        node: &Arc<Node>,
+        method: &str,
+        params: T1,
+    ) -> Result<T2, RpcError> {
+        let (id, req) = self.request(method, params);
+
+        if let Some(cassette) = self.replaying() {
+            return parse_response(cassette.play(method, &req)?, self.debug);
+        }
+
+        let span = info_span!("rpc", method, id, duration_ms = field::Empty);
+        let res = async {
+            self.throttle().await;
+
+            let start = Instant::now();
+            let res = node.send(method, id, &req).await;
+            self.observe(&[method], start, res.as_ref().map(|res| res.get("error").is_none()).unwrap_or(false));
+            res
+        }
+        .instrument(span)
+        .await;
+
+        let res = match res {
+            Ok(res) => res,
             Err(err) => {
-                error!("POST -> {} - {} FAIL", method, err);
-                return Err(RpcError::LowLevelError(err));
+                if err.is_transient() {
+                    self.pool.failover(node);
+                }
+                self.save_failure(method, &req, &err)?;
+                return Err(err);
             }
         };
+        self.save(method, &req, &res)?;
 
-        let res = res.json::<serde_json::Value>().await?;
-        if self.debug {
-            info!("Response: {}", res);
+        parse_response(res, self.debug)
+    }
+
+    pub async fn post_batch(&self, batch: Batch) -> Result<BatchResults, RpcError> {
+        let retriable = batch.calls.iter().all(|(method, _)| is_retriable(method));
+        let methods = batch.calls.iter().map(|(method, _)| method.clone()).collect::<Vec<_>>().join(",");
+        let (ids, reqs): (Vec<i64>, Vec<serde_json::Value>) =
+            batch.calls.iter().map(|(method, params)| self.request(method, params)).unzip();
+
+        if let Some(cassette) = self.replaying() {
+            let mut responses = HashMap::new();
+            for ((method, _), (id, req)) in batch.calls.iter().zip(ids.iter().zip(reqs.iter())) {
+                responses.insert(*id, cassette.play(method, req));
+            }
+            return Ok(BatchResults::new(&ids, responses, self.debug));
         }
 
-        if res.get("result").is_some() {
-            match serde_json::from_value::<T2>(res.get("result").unwrap().clone()) {
-                Ok(res) => return Ok(res),
-                Err(err) => return Err(RpcError::RpcApplicationResultParseError(err)),
+        let span = info_span!("rpc_batch", methods, calls = ids.len(), duration_ms = field::Empty);
+        let responses = async {
+            self.throttle().await;
+
+            let start = Instant::now();
+            let responses = self
+                .with_retry(&methods, retriable, |node| {
+                    let (methods, ids, reqs) = (&methods, &ids, &reqs);
+                    async move { node.send_batch(methods, ids, reqs).await }
+                })
+                .await;
+
+            // Every entry of a batch succeeds or fails on its own, a delivered batch may still carry errors
+            let elapsed = start.elapsed();
+            Span::current().record("duration_ms", elapsed.as_millis() as u64);
+            for ((method, _), id) in batch.calls.iter().zip(ids.iter()) {
+                let success = match &responses {
+                    Ok(responses) => matches!(responses.get(id), Some(Ok(res)) if res.get("error").is_none()),
+                    Err(_) => false,
+                };
+                self.metrics.observe(method, elapsed, success);
+            }
+            responses
+        }
+        .instrument(span)
+        .await;
+
+        let responses = match responses {
+            Ok(responses) => responses,
+            Err(err) => {
+                for ((method, _), req) in batch.calls.iter().zip(reqs.iter()) {
+                    self.save_failure(method, req, &err)?;
+                }
+                return Err(err);
+            }
+        };
+        for ((method, _), (id, req)) in batch.calls.iter().zip(ids.iter().zip(reqs.iter())) {
+            match responses.get(id) {
+                Some(Ok(res)) => self.save(method, req, res)?,
+                Some(Err(err)) => self.save_failure(method, req, err)?,
+                None => self.save_failure(method, req, &RpcError::Unknown)?,
+            }
+        }
+
+        Ok(BatchResults::new(&ids, responses, self.debug))
+    }
+
+    pub async fn subscribe<T1: serde::Serialize, T2: for<'de> serde::Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: T1,
+    ) -> Result<Subscription<T2>, RpcError> {
+        // Pushed values are not recorded, a recorded session must not depend on them to replay the same calls
+        if self.cassette.is_some() {
+            return Err(RpcError::CassetteMismatch(format!("{} could not be recorded", method)));
+        }
+
+        let node = self.pool.active();
+        self.verify_network(&node).await?;
+        let (id, req) = self.request(method, params);
+        let (res, receiver) = node.subscribe(id, &req).await?;
+        let _ = parse_response::<serde_json::Value>(res, self.debug)?;
+
+        info!("SUBSCRIBE -> {} SUCCESS", method);
+        Ok(Subscription::new(receiver))
+    }
+
+    pub async fn node_info(&self) -> Result<NodeInfo, RpcError> {
+        let node = self.pool.active();
+        if let Some(info) = node.info() {
+            return Ok(info);
+        }
+
+        let mut info = NodeInfo::parse(&self.post_node::<_, serde_json::Value>(&node, VERSION, json!([])).await?);
+        // A partial info is never cached, an empty network would fail every later network check
+        info.network = self.post_node::<_, String>(&node, STATE_NETWORK_NAME, json!([])).await?;
+        info!(
+            "Connected {} {:?} {} api {:#x} network {}",
+            node.url(),
+            info.node_impl,
+            info.version,
+            info.api_version,
+            info.network
+        );
+        node.set_info(info.clone());
+
+        Ok(info)
+    }
+
+    pub async fn connect(self) -> Result<Self, RpcError> {
+        self.node_info().await?;
+        Ok(self)
+    }
+
+    pub async fn check_health(&self, max_lag: i64) -> Result<(), RpcError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Head {
+            height: i64,
+        }
+
+        if self.replaying().is_some() {
+            return Ok(());
+        }
+
+        let mut best = None;
+        for node in self.pool.nodes().iter().filter(|node| !node.is_excluded()) {
+            let (id, req) = self.request(CHAIN_HEAD, Vec::<String>::new());
+            let head = match node.send(CHAIN_HEAD, id, &req).await {
+                Ok(res) => parse_response::<Head>(res, self.debug),
+                Err(err) => Err(err),
             };
+            match head {
+                Ok(head) => {
+                    node.set_height(head.height);
+                    node.set_healthy(true);
+                    best = best.max(Some(head.height));
+                }
+                Err(err) => {
+                    warn!("Rpc endpoint {} unhealthy: {}", node.url(), err);
+                    node.set_healthy(false);
+                }
+            }
         }
 
-        if res.get("error").is_some() {
-            return Err(RpcError::RpcApplicationError(res.get("error").unwrap().clone()));
+        let best = best.ok_or(RpcError::NoHealthyEndpoint)?;
+        for node in self.pool.nodes() {
+            if node.is_available() && node.height() + max_lag < best {
+                warn!("Rpc endpoint {} lags behind {} < {}", node.url(), node.height(), best);
+                node.set_healthy(false);
+            }
         }
 
-        Err(RpcError::Unknown)
+        match self.pool.select_healthy() {
+            true => Ok(()),
+            false => Err(RpcError::NoHealthyEndpoint),
+        }
+    }
+
+    // Runs until every other clone of the endpoint is dropped
+    pub fn spawn_health_check(&self, interval: Duration, max_lag: i64) {
+        let rpc = self.clone();
+        tokio::spawn(async move {
+            while Arc::strong_count(&rpc.pool) > 1 {
+                if let Err(err) = rpc.check_health(max_lag).await {
+                    warn!("Rpc health check fail: {}", err);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Answers every request with the network name as result, one request per connection
+    async fn serve(network: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/rpc/v0", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let req = loop {
+                    let mut chunk = [0u8; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let len = head
+                            .lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(str::to_string))
+                            .and_then(|len| len.trim().parse::<usize>().ok())
+                            .unwrap_or_default();
+                        if body.len() >= len {
+                            break serde_json::from_str::<serde_json::Value>(body).unwrap();
+                        }
+                    }
+                };
+                let body = match req {
+                    // Batched calls of an unknown method fail on their own
+                    serde_json::Value::Array(reqs) => serde_json::Value::Array(
+                        reqs.iter()
+                            .map(|req| match req["method"].as_str() {
+                                Some("Filecoin.Unknown") => json!({
+                                    "jsonrpc": "2.0",
+                                    "id": req["id"],
+                                    "error": {"code": -32601, "message": "method not found"}
+                                }),
+                                _ => json!({"jsonrpc": "2.0", "id": req["id"], "result": network}),
+                            })
+                            .collect(),
+                    )
+                    .to_string(),
+                    req => json!({"jsonrpc": "2.0", "id": req["id"], "result": network}).to_string(),
+                };
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn node_of_another_network_is_excluded() {
+        let mainnet = serve("mainnet").await;
+        let calibration = serve("calibrationnet").await;
+        let rpc = RpcEndpoint::new_pool(&[(&mainnet, ""), (&calibration, "")]).unwrap().network("calibrationnet");
+
+        assert_eq!(rpc.post::<_, String>("Filecoin.ChainHead", json!([])).await.unwrap(), "calibrationnet");
+        assert_eq!(rpc.url().as_str(), calibration);
+        assert!(rpc.pool.nodes()[0].is_excluded());
+    }
+
+    #[tokio::test]
+    async fn node_info_is_cached_on_the_node_that_served_it() {
+        let calibration = serve("calibrationnet").await;
+        // Nothing listens on port 1
+        let rpc = RpcEndpoint::new_pool(&[("http://127.0.0.1:1/rpc/v0", ""), (&calibration, "")])
+            .unwrap()
+            .retry_policy(RetryPolicy::never());
+
+        assert!(rpc.node_info().await.is_err());
+        assert!(rpc.pool.nodes()[0].info().is_none());
+        assert!(rpc.pool.nodes()[1].info().is_none());
+
+        assert_eq!(rpc.node_info().await.unwrap().network, "calibrationnet");
+        assert!(rpc.pool.nodes()[0].info().is_none());
+        assert_eq!(rpc.pool.nodes()[1].info().unwrap().network, "calibrationnet");
+    }
+
+    #[tokio::test]
+    async fn batch_metrics_count_failed_entries() {
+        let mainnet = serve("mainnet").await;
+        let rpc = RpcEndpoint::new_pool(&[(&mainnet, "")]).unwrap();
+
+        let mut batch = Batch::new();
+        let head = batch.call::<_, String>("Filecoin.ChainHead", json!([]));
+        let unknown = batch.call::<_, String>("Filecoin.Unknown", json!([]));
+        let mut res = rpc.post_batch(batch).await.unwrap();
+        assert_eq!(res.take(head).unwrap(), "mainnet");
+        assert!(res.take(unknown).is_err());
+
+        let metrics = rpc.metrics();
+        assert_eq!((metrics["Filecoin.ChainHead"].requests, metrics["Filecoin.ChainHead"].errors), (1, 0));
+        assert_eq!((metrics["Filecoin.Unknown"].requests, metrics["Filecoin.Unknown"].errors), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn no_node_of_the_network_left() {
+        let mainnet = serve("mainnet").await;
+        let rpc = RpcEndpoint::new_pool(&[(&mainnet, "")]).unwrap().network("calibrationnet");
+
+        assert!(matches!(
+            rpc.post::<_, String>("Filecoin.ChainHead", json!([])).await,
+            Err(RpcError::NetworkMismatch(expected, network)) if expected == "calibrationnet" && network == "mainnet"
+        ));
     }
 }
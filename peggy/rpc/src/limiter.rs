@@ -0,0 +1,40 @@
+use std::time::Duration;
+use tokio::{sync::Mutex, time::Instant};
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+pub(crate) struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: u32) -> Self {
+        let rate = requests_per_second.max(1) as f64;
+        Self { rate, burst: rate, bucket: Mutex::new(Bucket { tokens: rate, last: Instant::now() }) }
+    }
+
+    // Callers queue on the bucket lock so waiting requests are served in order
+    pub(crate) async fn acquire(&self) -> Duration {
+        let mut bucket = self.bucket.lock().await;
+
+        let now = Instant::now();
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.last).as_secs_f64() * self.rate).min(self.burst);
+        bucket.last = now;
+
+        let mut wait = Duration::ZERO;
+        if bucket.tokens < 1.0 {
+            wait = Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate);
+            tokio::time::sleep(wait).await;
+            bucket.tokens = 1.0;
+            bucket.last = Instant::now();
+        }
+        bucket.tokens -= 1.0;
+
+        wait
+    }
+}
@@ -0,0 +1,111 @@
+use thiserror::Error;
+
+const METHOD_NOT_FOUND_CODE: i64 = -32601;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LotusError {
+    #[error("message nonce too low: {0}")]
+    NonceTooLow(String),
+    #[error("message nonce gap: {0}")]
+    NonceGap(String),
+    #[error("message already in mpool: {0}")]
+    AlreadyInMpool(String),
+    #[error("another message with the nonce exists: {0}")]
+    ExistingNonce(String),
+    #[error("insufficient funds: {0}")]
+    InsufficientFunds(String),
+    #[error("actor not found: {0}")]
+    ActorNotFound(String),
+    #[error("method not found: {0}")]
+    MethodNotFound(String),
+    #[error("lotus error {code}: {message}")]
+    Other { code: i64, message: String },
+}
+
+impl LotusError {
+    pub fn parse(err: &serde_json::Value) -> Option<Self> {
+        let message = err.get("message")?.as_str()?.to_string();
+        let code = err.get("code").and_then(|code| code.as_i64()).unwrap_or_default();
+        let lower = message.to_lowercase();
+
+        let err = if code == METHOD_NOT_FOUND_CODE {
+            Self::MethodNotFound(message)
+        } else if lower.contains("nonce too low") {
+            Self::NonceTooLow(message)
+        } else if lower.contains("nonce gap") {
+            Self::NonceGap(message)
+        } else if lower.contains("already in mpool") {
+            Self::AlreadyInMpool(message)
+        } else if lower.contains("with nonce already exists") {
+            Self::ExistingNonce(message)
+        } else if lower.contains("insufficient funds")
+            || lower.contains("not enough funds")
+            || lower.contains("insufficient balance")
+        {
+            Self::InsufficientFunds(message)
+        } else if lower.contains("actor not found") || lower.contains("resolution lookup failed") {
+            Self::ActorNotFound(message)
+        } else {
+            Self::Other { code, message }
+        };
+
+        Some(err)
+    }
+
+    // The very same signed message is already pending, pushing it again is not a conflict
+    pub fn is_already_in_mpool(&self) -> bool {
+        matches!(self, Self::AlreadyInMpool(_))
+    }
+
+    pub fn is_nonce_conflict(&self) -> bool {
+        matches!(self, Self::NonceTooLow(_) | Self::NonceGap(_) | Self::ExistingNonce(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::LotusError;
+
+    fn parse(code: i64, message: &str) -> LotusError {
+        LotusError::parse(&json!({"code": code, "message": message})).unwrap()
+    }
+
+    #[test]
+    fn classifies_mpool_push_errors() {
+        assert!(matches!(
+            parse(1, "message nonce too low: nonce 3 < 5: message nonce too low"),
+            LotusError::NonceTooLow(_)
+        ));
+        assert!(matches!(parse(1, "add message: nonce gap: 7 > 5"), LotusError::NonceGap(_)));
+        assert!(matches!(parse(1, "message already in mpool"), LotusError::AlreadyInMpool(_)));
+        assert!(matches!(
+            parse(1, "replace by fee has too low GasPremium: message with nonce already exists"),
+            LotusError::ExistingNonce(_)
+        ));
+        assert!(matches!(
+            parse(1, "not enough funds including cost of gas: 0 < 100"),
+            LotusError::InsufficientFunds(_)
+        ));
+    }
+
+    #[test]
+    fn classifies_lookup_and_method_errors() {
+        assert!(matches!(parse(1, "resolution lookup failed (t3abc): actor not found"), LotusError::ActorNotFound(_)));
+        assert!(matches!(parse(-32601, "method 'Filecoin.StateLookupID' not found"), LotusError::MethodNotFound(_)));
+        assert_eq!(parse(1, "boom"), LotusError::Other { code: 1, message: "boom".to_string() });
+        assert!(LotusError::parse(&json!({"code": 1})).is_none());
+    }
+
+    #[test]
+    fn already_in_mpool_is_not_a_nonce_conflict() {
+        let err = parse(1, "message already in mpool");
+        assert!(err.is_already_in_mpool());
+        assert!(!err.is_nonce_conflict());
+
+        assert!(parse(1, "message nonce too low").is_nonce_conflict());
+        assert!(parse(1, "nonce gap").is_nonce_conflict());
+        assert!(parse(1, "message with nonce already exists").is_nonce_conflict());
+    }
+}
@@ -0,0 +1,54 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Clone, Debug, Default)]
+pub struct MethodMetrics {
+    pub requests: u64,
+    pub errors: u64,
+    pub total_latency: Duration,
+    // Cumulative counts of requests finished within LATENCY_BUCKETS_MS, the last one is +Inf
+    pub latency_buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+}
+
+impl MethodMetrics {
+    fn observe(&mut self, latency: Duration, success: bool) {
+        self.requests += 1;
+        if !success {
+            self.errors += 1;
+        }
+        self.total_latency += latency;
+
+        let latency = latency.as_millis() as u64;
+        for (i, bucket) in LATENCY_BUCKETS_MS.iter().enumerate() {
+            if latency <= *bucket {
+                self.latency_buckets[i] += 1;
+            }
+        }
+        self.latency_buckets[LATENCY_BUCKETS_MS.len()] += 1;
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+    throttled: Mutex<Duration>,
+}
+
+impl Metrics {
+    pub(crate) fn observe(&self, method: &str, latency: Duration, success: bool) {
+        self.methods.lock().unwrap().entry(method.to_string()).or_default().observe(latency, success);
+    }
+
+    pub(crate) fn throttle(&self, wait: Duration) {
+        *self.throttled.lock().unwrap() += wait;
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    pub(crate) fn throttled(&self) -> Duration {
+        *self.throttled.lock().unwrap()
+    }
+}
@@ -0,0 +1,202 @@
+use log::{error, info};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Client,
+};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{
+            AtomicBool,
+            AtomicI64,
+            Ordering::{Relaxed, SeqCst},
+        },
+        RwLock,
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use url::Url;
+
+use crate::{ws::WsTransport, NodeInfo, RpcError};
+
+#[derive(Clone)]
+enum Transport {
+    Http(Client),
+    Ws(WsTransport),
+}
+
+pub(crate) struct Node {
+    url: Url,
+    bearer_token: String,
+    transport: Transport,
+    healthy: AtomicBool,
+    // Serves another network than the endpoint signs for, never selected again
+    excluded: AtomicBool,
+    height: AtomicI64,
+    info: RwLock<Option<NodeInfo>>,
+    network: RwLock<Option<String>>,
+}
+
+impl Node {
+    pub(crate) fn new(url: &str, bearer_token: &str) -> Result<Self, RpcError> {
+        let url = Url::parse(url)?;
+
+        let transport = match url.scheme() {
+            "ws" | "wss" => Transport::Ws(WsTransport::new(url.clone(), bearer_token)),
+            _ => Transport::Http(Client::builder().timeout(Duration::from_secs(600)).build()?),
+        };
+
+        Ok(Self {
+            url,
+            bearer_token: bearer_token.to_string(),
+            transport,
+            healthy: AtomicBool::new(true),
+            excluded: AtomicBool::new(false),
+            height: AtomicI64::new(0),
+            info: RwLock::new(None),
+            network: RwLock::new(None),
+        })
+    }
+
+    pub(crate) fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub(crate) fn is_websocket(&self) -> bool {
+        matches!(self.transport, Transport::Ws(_))
+    }
+
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.healthy.load(Relaxed)
+    }
+
+    pub(crate) fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, SeqCst);
+    }
+
+    pub(crate) fn is_excluded(&self) -> bool {
+        self.excluded.load(Relaxed)
+    }
+
+    pub(crate) fn exclude(&self) {
+        self.excluded.store(true, SeqCst);
+    }
+
+    pub(crate) fn is_available(&self) -> bool {
+        self.is_healthy() && !self.is_excluded()
+    }
+
+    pub(crate) fn height(&self) -> i64 {
+        self.height.load(Relaxed)
+    }
+
+    pub(crate) fn set_height(&self, height: i64) {
+        self.height.store(height, SeqCst);
+    }
+
+    pub(crate) fn info(&self) -> Option<NodeInfo> {
+        self.info.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_info(&self, info: NodeInfo) {
+        *self.info.write().unwrap() = Some(info);
+    }
+
+    pub(crate) fn network(&self) -> Option<String> {
+        self.network.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_network(&self, network: &str) {
+        *self.network.write().unwrap() = Some(network.to_string());
+    }
+
+    async fn post_http(
+        &self,
+        cli: &Client,
+        method: &str,
+        req: &serde_json::Value,
+    ) -> Result<serde_json::Value, RpcError> {
+        let res = cli
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, format!("Bearer {}", self.bearer_token))
+            .json(req)
+            .send()
+            .await?;
+
+        let res = match res.error_for_status() {
+            Ok(res) => {
+                info!("POST {} -> {} SUCCESS", self.url, method);
+                res
+            }
+            Err(err) => {
+                error!("POST {} -> {} - {} FAIL", self.url, method, err);
+                return Err(RpcError::LowLevelError(err));
+            }
+        };
+
+        Ok(res.json::<serde_json::Value>().await?)
+    }
+
+    pub(crate) async fn send(
+        &self,
+        method: &str,
+        id: i64,
+        req: &serde_json::Value,
+    ) -> Result<serde_json::Value, RpcError> {
+        match &self.transport {
+            Transport::Http(cli) => self.post_http(cli, method, req).await,
+            Transport::Ws(ws) => match ws.request(id, req).await {
+                Ok(res) => {
+                    info!("WS {} -> {} SUCCESS", self.url, method);
+                    Ok(res)
+                }
+                Err(err) => {
+                    error!("WS {} -> {} - {} FAIL", self.url, method, err);
+                    Err(err)
+                }
+            },
+        }
+    }
+
+    pub(crate) async fn send_batch(
+        &self,
+        methods: &str,
+        ids: &[i64],
+        reqs: &[serde_json::Value],
+    ) -> Result<HashMap<i64, Result<serde_json::Value, RpcError>>, RpcError> {
+        let mut responses = HashMap::new();
+
+        match &self.transport {
+            Transport::Http(cli) => {
+                let res = match self.post_http(cli, methods, &json!(reqs)).await? {
+                    serde_json::Value::Array(res) => res,
+                    res => vec![res],
+                };
+                for res in res {
+                    if let Some(id) = res.get("id").and_then(|id| id.as_i64()) {
+                        responses.insert(id, Ok(res));
+                    }
+                }
+            }
+            Transport::Ws(ws) => {
+                responses = ws.request_batch(ids, reqs).await?;
+            }
+        }
+
+        Ok(responses)
+    }
+
+    pub(crate) async fn subscribe(
+        &self,
+        id: i64,
+        req: &serde_json::Value,
+    ) -> Result<(serde_json::Value, mpsc::UnboundedReceiver<serde_json::Value>), RpcError> {
+        match &self.transport {
+            Transport::Ws(ws) => ws.subscribe(id, req).await,
+            Transport::Http(_) => Err(RpcError::SubscriptionUnsupported),
+        }
+    }
+}
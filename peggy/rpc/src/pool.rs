@@ -0,0 +1,188 @@
+use log::warn;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::node::Node;
+
+// Pushing a signed message twice may double-send it, so these are never retried nor failed over
+const NON_IDEMPOTENT_METHODS: [&str; 6] = [
+    "Filecoin.MpoolPush",
+    "Filecoin.MpoolPushMessage",
+    "Filecoin.MpoolPushUntrusted",
+    "Filecoin.MpoolBatchPush",
+    "Filecoin.MpoolBatchPushMessage",
+    "Filecoin.MpoolBatchPushUntrusted",
+];
+
+// Blocks on the node until the message reaches its confidence, a timeout is expected and no sign of a bad node
+const LONG_POLL_METHODS: [&str; 2] = ["Filecoin.StateWaitMsg", "Filecoin.StateWaitMsgLimited"];
+
+pub fn is_idempotent(method: &str) -> bool {
+    !NON_IDEMPOTENT_METHODS.contains(&method)
+}
+
+pub fn is_long_poll(method: &str) -> bool {
+    LONG_POLL_METHODS.contains(&method)
+}
+
+pub fn is_retriable(method: &str) -> bool {
+    is_idempotent(method) && !is_long_poll(method)
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: 3, initial_backoff: Duration::from_millis(500), max_backoff: Duration::from_secs(10) }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self { max_retries: 0, ..Default::default() }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt));
+        backoff.min(self.max_backoff)
+    }
+}
+
+pub(crate) struct NodePool {
+    nodes: Vec<Arc<Node>>,
+    active: AtomicUsize,
+}
+
+impl NodePool {
+    pub(crate) fn new(nodes: Vec<Node>) -> Self {
+        Self { nodes: nodes.into_iter().map(Arc::new).collect(), active: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    pub(crate) fn active(&self) -> Arc<Node> {
+        self.nodes[self.active.load(SeqCst) % self.nodes.len()].clone()
+    }
+
+    pub(crate) fn failover(&self, failed: &Arc<Node>) {
+        failed.set_healthy(false);
+
+        if self.nodes.iter().all(|node| !node.is_available()) {
+            warn!("No healthy rpc endpoint left, reset all endpoints");
+            self.nodes.iter().for_each(|node| node.set_healthy(true));
+        }
+
+        self.select_next(failed);
+    }
+
+    // Excluded nodes survive the reset of unhealthy ones, false once every node is excluded
+    pub(crate) fn exclude(&self, failed: &Arc<Node>) -> bool {
+        failed.exclude();
+        self.select_next(failed)
+    }
+
+    fn select_next(&self, failed: &Arc<Node>) -> bool {
+        let start = self.active.load(SeqCst);
+        for i in 1..=self.nodes.len() {
+            let index = (start + i) % self.nodes.len();
+            if self.nodes[index].is_available() {
+                if index != start % self.nodes.len() {
+                    warn!("Fail over {} -> {}", failed.url(), self.nodes[index].url());
+                }
+                self.active.store(index, SeqCst);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(crate) fn select_healthy(&self) -> bool {
+        let active = self.active.load(SeqCst) % self.nodes.len();
+        if self.nodes[active].is_available() {
+            return true;
+        }
+
+        match self.nodes.iter().position(|node| node.is_available()) {
+            Some(index) => {
+                warn!("Switch rpc endpoint {} -> {}", self.nodes[active].url(), self.nodes[index].url());
+                self.active.store(index, SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(count: usize) -> NodePool {
+        let nodes = (0..count).map(|i| Node::new(&format!("http://127.0.0.1:{}/rpc/v0", 1234 + i), "").unwrap());
+        NodePool::new(nodes.collect())
+    }
+
+    #[test]
+    fn long_poll_methods_are_not_retried() {
+        assert!(is_retriable("Filecoin.ChainHead"));
+        assert!(!is_retriable("Filecoin.StateWaitMsg"));
+        assert!(!is_retriable("Filecoin.StateWaitMsgLimited"));
+        assert!(!is_retriable("Filecoin.MpoolPush"));
+        // Waiting twice is harmless, only the retry loop has to leave it alone
+        assert!(is_idempotent("Filecoin.StateWaitMsg"));
+    }
+
+    #[test]
+    fn exclude_moves_to_next_available_node() {
+        let pool = pool(3);
+        let first = pool.active();
+        assert!(pool.exclude(&first));
+        assert_eq!(pool.active().url().port(), Some(1235));
+
+        let second = pool.active();
+        assert!(pool.exclude(&second));
+        assert_eq!(pool.active().url().port(), Some(1236));
+
+        let third = pool.active();
+        assert!(!pool.exclude(&third));
+    }
+
+    #[test]
+    fn failover_reset_keeps_excluded_nodes_out() {
+        let pool = pool(2);
+        let first = pool.active();
+        assert!(pool.exclude(&first));
+
+        // The only available node fails, the reset must not bring the excluded one back
+        let second = pool.active();
+        pool.failover(&second);
+        assert_eq!(pool.active().url().port(), Some(1235));
+        assert!(second.is_healthy());
+        assert!(!first.is_available());
+        assert!(pool.select_healthy());
+    }
+
+    #[test]
+    fn select_healthy_skips_excluded_nodes() {
+        let pool = pool(3);
+        pool.nodes()[1].exclude();
+        pool.nodes()[0].set_healthy(false);
+        assert!(pool.select_healthy());
+        assert_eq!(pool.active().url().port(), Some(1236));
+
+        pool.nodes()[2].set_healthy(false);
+        assert!(!pool.select_healthy());
+    }
+}
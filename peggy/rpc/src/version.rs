@@ -0,0 +1,102 @@
+pub const VERSION: &str = "Filecoin.Version";
+pub const STATE_NETWORK_NAME: &str = "Filecoin.StateNetworkName";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeImpl {
+    #[default]
+    Lotus,
+    Forest,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NodeInfo {
+    pub node_impl: NodeImpl,
+    pub version: String,
+    pub api_version: u32,
+    pub block_delay: u64,
+    // Filled by RpcEndpoint::node_info, Version does not report it
+    pub network: String,
+}
+
+impl NodeInfo {
+    // Lotus and Forest don't agree on the casing of Version result
+    pub fn parse(res: &serde_json::Value) -> Self {
+        let field = |keys: [&str; 2]| keys.iter().find_map(|key| res.get(key));
+
+        let version = field(["Version", "version"]).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let api_version = match field(["APIVersion", "api_version"]) {
+            Some(serde_json::Value::Array(v)) => v
+                .iter()
+                .take(3)
+                .map(|v| v.as_u64().unwrap_or_default() as u32)
+                .fold(0, |api_version, v| (api_version << 8) | v),
+            Some(v) => v.as_u64().unwrap_or_default() as u32,
+            None => 0,
+        };
+        let block_delay = field(["BlockDelay", "block_delay"]).and_then(|v| v.as_u64()).unwrap_or_default();
+
+        let node_impl = match version.to_lowercase().contains("forest") {
+            true => NodeImpl::Forest,
+            false => NodeImpl::Lotus,
+        };
+
+        Self { node_impl, version, api_version, block_delay, network: String::new() }
+    }
+
+    pub fn api_major(&self) -> u32 {
+        self.api_version >> 16
+    }
+
+    pub fn is_forest(&self) -> bool {
+        self.node_impl == NodeImpl::Forest
+    }
+
+    // Lotus full node api v0 reports 1.x, v1 reports 2.x
+    pub fn is_api_v1(&self) -> bool {
+        self.node_impl == NodeImpl::Lotus && self.api_major() >= 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{NodeImpl, NodeInfo};
+
+    #[test]
+    fn parse_lotus_v1_version() {
+        let info = NodeInfo::parse(
+            &json!({"Version": "1.23.0+mainnet+git.d9bd5c2", "APIVersion": 0x00020300, "BlockDelay": 30}),
+        );
+        assert_eq!(info.node_impl, NodeImpl::Lotus);
+        assert_eq!(info.version, "1.23.0+mainnet+git.d9bd5c2");
+        assert_eq!((info.api_major(), info.block_delay), (2, 30));
+        assert!(info.is_api_v1() && !info.is_forest());
+    }
+
+    #[test]
+    fn parse_lotus_v0_version() {
+        let info = NodeInfo::parse(&json!({"Version": "1.19.0+calibnet", "APIVersion": 0x00010500, "BlockDelay": 30}));
+        assert_eq!(info.node_impl, NodeImpl::Lotus);
+        assert_eq!(info.api_major(), 1);
+        assert!(!info.is_api_v1());
+    }
+
+    #[test]
+    fn parse_forest_version() {
+        let info = NodeInfo::parse(
+            &json!({"version": "forest-0.4.1+git.3c3e9a5", "api_version": [1, 5, 0], "block_delay": 30}),
+        );
+        assert_eq!(info.node_impl, NodeImpl::Forest);
+        assert_eq!(info.api_version, 0x00010500);
+        assert_eq!(info.block_delay, 30);
+        assert!(info.is_forest() && !info.is_api_v1());
+    }
+
+    #[test]
+    fn parse_unknown_payload_defaults_to_lotus() {
+        let info = NodeInfo::parse(&json!("calibrationnet"));
+        assert_eq!(info.node_impl, NodeImpl::Lotus);
+        assert_eq!((info.version.as_str(), info.api_version), ("", 0));
+    }
+}
@@ -0,0 +1,331 @@
+use futures_util::{SinkExt, StreamExt};
+use log::{error, warn};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        http::{header::AUTHORIZATION, HeaderValue},
+        Message,
+    },
+};
+use url::Url;
+
+use crate::RpcError;
+
+const CHANNEL_VALUE_METHOD: &str = "xrpc.ch.val";
+const CHANNEL_CLOSE_METHOD: &str = "xrpc.ch.close";
+
+type Pending = Arc<Mutex<HashMap<i64, oneshot::Sender<Value>>>>;
+type Channels = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>;
+type PendingSubscriptions = Arc<Mutex<HashMap<i64, mpsc::UnboundedSender<Value>>>>;
+
+struct WsConnection {
+    sender: mpsc::UnboundedSender<Message>,
+    pending: Pending,
+    pending_subscriptions: PendingSubscriptions,
+    // The writer keeps its receiver after the socket died, so the sender alone never reports it
+    closed: Arc<AtomicBool>,
+}
+
+impl WsConnection {
+    async fn connect(url: &Url, bearer_token: &str) -> Result<Self, RpcError> {
+        let mut req = url.as_str().into_client_request()?;
+        if !bearer_token.is_empty() {
+            let token =
+                HeaderValue::from_str(&format!("Bearer {}", bearer_token)).map_err(|_| RpcError::InvalidBearerToken)?;
+            req.headers_mut().insert(AUTHORIZATION, token);
+        }
+
+        let (stream, _) = connect_async(req).await?;
+        let (mut write, mut read) = stream.split();
+
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+        let _closed = closed.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                if let Err(err) = write.send(msg).await {
+                    error!("WS write fail: {}", err);
+                    _closed.store(true, SeqCst);
+                    break;
+                }
+            }
+        });
+
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let pending_subscriptions: PendingSubscriptions = Arc::new(Mutex::new(HashMap::new()));
+        let channels: Channels = Arc::new(Mutex::new(HashMap::new()));
+
+        let _pending = pending.clone();
+        let _pending_subscriptions = pending_subscriptions.clone();
+        let _closed = closed.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = read.next().await {
+                let text = match msg {
+                    Ok(Message::Text(text)) => text,
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => continue,
+                    Err(err) => {
+                        error!("WS read fail: {}", err);
+                        break;
+                    }
+                };
+                let res = match serde_json::from_str::<Value>(&text) {
+                    Ok(res) => res,
+                    Err(err) => {
+                        warn!("WS invalid frame {}: {}", text, err);
+                        continue;
+                    }
+                };
+                // A batch is answered with one array frame, its replies are matched by id like single ones
+                match res {
+                    Value::Array(res) => {
+                        for res in res {
+                            Self::dispatch(res, &_pending, &_pending_subscriptions, &channels);
+                        }
+                    }
+                    res => Self::dispatch(res, &_pending, &_pending_subscriptions, &channels),
+                }
+            }
+
+            // Flag first, a request enqueued after the clear sees the flag and never waits on a dead socket
+            _closed.store(true, SeqCst);
+            _pending.lock().unwrap().clear();
+            _pending_subscriptions.lock().unwrap().clear();
+            channels.lock().unwrap().clear();
+        });
+
+        Ok(Self { sender, pending, pending_subscriptions, closed })
+    }
+
+    fn dispatch(res: Value, pending: &Pending, pending_subscriptions: &PendingSubscriptions, channels: &Channels) {
+        match res.get("method").and_then(|m| m.as_str()) {
+            Some(CHANNEL_VALUE_METHOD) => {
+                let chan = res.get("params").and_then(|p| p.get(0)).map(|c| c.to_string()).unwrap_or_default();
+                let value = res.get("params").and_then(|p| p.get(1)).cloned().unwrap_or(Value::Null);
+                let mut channels = channels.lock().unwrap();
+                if let Some(tx) = channels.get(&chan) {
+                    if tx.send(value).is_err() {
+                        channels.remove(&chan);
+                    }
+                }
+                return;
+            }
+            Some(CHANNEL_CLOSE_METHOD) => {
+                let chan = res.get("params").and_then(|p| p.get(0)).map(|c| c.to_string()).unwrap_or_default();
+                channels.lock().unwrap().remove(&chan);
+                return;
+            }
+            _ => {}
+        }
+
+        let id = match res.get("id").and_then(|id| id.as_i64()) {
+            Some(id) => id,
+            None => return,
+        };
+
+        if let Some(tx) = pending_subscriptions.lock().unwrap().remove(&id) {
+            if let Some(chan) = res.get("result") {
+                channels.lock().unwrap().insert(chan.to_string(), tx);
+            }
+        }
+        if let Some(tx) = pending.lock().unwrap().remove(&id) {
+            let _ = tx.send(res);
+        }
+    }
+
+    async fn request(
+        &self,
+        id: i64,
+        req: &Value,
+        subscription: Option<mpsc::UnboundedSender<Value>>,
+    ) -> Result<Value, RpcError> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        if let Some(subscription) = subscription {
+            self.pending_subscriptions.lock().unwrap().insert(id, subscription);
+        }
+
+        if self.is_closed() || self.sender.send(Message::Text(req.to_string())).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            self.pending_subscriptions.lock().unwrap().remove(&id);
+            return Err(RpcError::ConnectionClosed);
+        }
+
+        match tokio::time::timeout(Duration::from_secs(600), rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(RpcError::ConnectionClosed),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                self.pending_subscriptions.lock().unwrap().remove(&id);
+                Err(RpcError::Timeout)
+            }
+        }
+    }
+
+    async fn request_batch(
+        &self,
+        ids: &[i64],
+        reqs: &[Value],
+    ) -> Result<HashMap<i64, Result<Value, RpcError>>, RpcError> {
+        let mut receivers = Vec::new();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for id in ids {
+                let (tx, rx) = oneshot::channel();
+                pending.insert(*id, tx);
+                receivers.push((*id, rx));
+            }
+        }
+
+        if self.is_closed() || self.sender.send(Message::Text(Value::Array(reqs.to_vec()).to_string())).is_err() {
+            let mut pending = self.pending.lock().unwrap();
+            for id in ids {
+                pending.remove(id);
+            }
+            return Err(RpcError::ConnectionClosed);
+        }
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(600);
+        let mut responses = HashMap::new();
+        for (id, rx) in receivers {
+            let res = match tokio::time::timeout_at(deadline, rx).await {
+                Ok(Ok(res)) => Ok(res),
+                Ok(Err(_)) => Err(RpcError::ConnectionClosed),
+                Err(_) => {
+                    self.pending.lock().unwrap().remove(&id);
+                    Err(RpcError::Timeout)
+                }
+            };
+            responses.insert(id, res);
+        }
+        Ok(responses)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(SeqCst) || self.sender.is_closed()
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct WsTransport {
+    url: Url,
+    bearer_token: String,
+    conn: Arc<AsyncMutex<Option<Arc<WsConnection>>>>,
+}
+
+impl WsTransport {
+    pub(crate) fn new(url: Url, bearer_token: &str) -> Self {
+        Self { url, bearer_token: bearer_token.to_string(), conn: Arc::new(AsyncMutex::new(None)) }
+    }
+
+    async fn connection(&self) -> Result<Arc<WsConnection>, RpcError> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = conn.as_ref() {
+            if !c.is_closed() {
+                return Ok(c.clone());
+            }
+        }
+
+        let c = Arc::new(WsConnection::connect(&self.url, &self.bearer_token).await?);
+        *conn = Some(c.clone());
+        Ok(c)
+    }
+
+    pub(crate) async fn request(&self, id: i64, req: &Value) -> Result<Value, RpcError> {
+        self.connection().await?.request(id, req, None).await
+    }
+
+    pub(crate) async fn request_batch(
+        &self,
+        ids: &[i64],
+        reqs: &[Value],
+    ) -> Result<HashMap<i64, Result<Value, RpcError>>, RpcError> {
+        self.connection().await?.request_batch(ids, reqs).await
+    }
+
+    pub(crate) async fn subscribe(
+        &self,
+        id: i64,
+        req: &Value,
+    ) -> Result<(Value, mpsc::UnboundedReceiver<Value>), RpcError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let res = self.connection().await?.request(id, req, Some(tx)).await?;
+        Ok((res, rx))
+    }
+}
+
+pub struct Subscription<T> {
+    receiver: mpsc::UnboundedReceiver<Value>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: for<'de> serde::Deserialize<'de>> Subscription<T> {
+    pub(crate) fn new(receiver: mpsc::UnboundedReceiver<Value>) -> Self {
+        Self { receiver, _marker: PhantomData }
+    }
+
+    // Channel values are plain json, a subscription can be reinterpreted once the caller knows their type
+    pub fn cast<U: for<'de> serde::Deserialize<'de>>(self) -> Subscription<U> {
+        Subscription::new(self.receiver)
+    }
+
+    pub async fn next(&mut self) -> Option<Result<T, RpcError>> {
+        let value = self.receiver.recv().await?;
+        Some(serde_json::from_value::<T>(value).map_err(RpcError::RpcApplicationResultParseError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn batch_is_sent_as_one_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}/rpc/v0", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let reqs = match ws.next().await.unwrap().unwrap() {
+                Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+                msg => panic!("unexpected frame {msg:?}"),
+            };
+            let reqs = reqs.as_array().unwrap().clone();
+            // Replies come back out of order
+            let res: Vec<Value> = reqs
+                .iter()
+                .rev()
+                .map(|req| json!({"jsonrpc": "2.0", "id": req["id"], "result": req["method"]}))
+                .collect();
+            ws.send(Message::Text(Value::Array(res).to_string())).await.unwrap();
+            reqs.len()
+        });
+
+        let ws = WsTransport::new(url, "");
+        let reqs = vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "Filecoin.ChainHead", "params": []}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "Filecoin.Version", "params": []}),
+        ];
+        let res = ws.request_batch(&[1, 2], &reqs).await.unwrap();
+
+        assert_eq!(server.await.unwrap(), 2);
+        assert_eq!(res[&1].as_ref().unwrap()["result"], "Filecoin.ChainHead");
+        assert_eq!(res[&2].as_ref().unwrap()["result"], "Filecoin.Version");
+    }
+}
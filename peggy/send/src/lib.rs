@@ -1,9 +1,9 @@
 use forest_json::cid::CidJson;
 use forest_key_management::KeyInfo;
 use fvm_shared::{address::Address, econ::TokenAmount};
-use mpool::{mpool_push, MpoolError};
+use mpool::{mpool_dry_run, mpool_push, MpoolError, Simulation};
 use rpc::RpcEndpoint;
-use state::{wait_msg, StateError};
+use state::{wait_msg_receipt, AnyAddress, StateError, WaitOptions};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,14 +18,26 @@ pub async fn send(
     rpc: RpcEndpoint,
     from: Address,
     from_key_info: KeyInfo,
-    to: Address,
+    to: impl Into<AnyAddress>,
     value: TokenAmount,
 ) -> Result<CidJson, SendError> {
-    match mpool_push::<_, CidJson>(rpc.clone(), from, from_key_info, to, 0, value, Vec::<CidJson>::new()).await {
+    match mpool_push(rpc.clone(), from, from_key_info, to, 0, value, Vec::<CidJson>::new()).await {
         Ok(res) => {
-            let _ = wait_msg::<serde_json::Value>(rpc, res.clone()).await?;
-            Ok(res)
+            let receipt = wait_msg_receipt::<_, serde_json::Value>(rpc, res, WaitOptions::default()).await?;
+            Ok(receipt.executed)
         }
         Err(err) => Err(SendError::MpoolCallError(err)),
     }
 }
+
+pub async fn simulate_send(
+    rpc: RpcEndpoint,
+    from: Address,
+    to: impl Into<AnyAddress>,
+    value: TokenAmount,
+) -> Result<Simulation, SendError> {
+    match mpool_dry_run(rpc, from, to, 0, value, Vec::<CidJson>::new()).await {
+        Ok(simulation) => Ok(simulation),
+        Err(err) => Err(SendError::MpoolCallError(err)),
+    }
+}
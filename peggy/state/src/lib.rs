@@ -1,46 +1,62 @@
+use cid::Cid;
 use forest_blocks::tipset_keys_json::TipsetKeysJson;
 use forest_ipld::{json::IpldJson, Ipld};
 use forest_json::cid::CidJson;
-use forest_rpc_api::state_api;
-use fvm_shared::{address::Address, error::ExitCode};
-use log::warn;
-use rpc::{RpcEndpoint, RpcError};
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::{
-    fmt::{self, Debug},
-    str::FromStr,
+use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode};
+use log::{info, warn};
+pub use lotusapi::{
+    is_address_of_network,
+    with_network_prefix,
+    AddressResolver,
+    AnyAddress,
+    BeneficiaryTerm,
+    Claim,
+    DeadlineInfo,
+    ExecutionTrace,
+    GasCharge,
+    MessageLookup,
+    MinerInfo,
+    MinerPower,
+    MinerSectors,
+    ReceiptJson,
+    TraceMessage,
+    TraceReceipt,
+    FINALITY,
 };
+use lotusapi::{LotusApi, LOOKBACK_NO_LIMIT};
+use rpc::{LotusError, RpcError, Subscription};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::{Duration, Instant};
 use thiserror::Error;
+pub use tokio_util::sync::CancellationToken;
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-pub struct ReceiptJson {
-    exit_code: ExitCode,
-    #[serde(rename = "Return")]
-    return_data: Option<String>,
-    gas_used: i64,
+mod watcher;
+
+pub use watcher::{ChainEvent, ChainWatcher, TipsetRef};
+
+pub const DEFAULT_CONFIDENCE: i64 = 10;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadChangeType {
+    Current,
+    Apply,
+    Revert,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 #[serde(rename_all = "PascalCase")]
-pub struct MessageLookup {
-    pub receipt: ReceiptJson,
-    #[serde(rename = "TipSet")]
-    pub tipset: TipsetKeysJson,
+pub struct Tipset {
+    pub cids: TipsetKeysJson,
     pub height: i64,
-    pub message: CidJson,
-    pub return_dec: IpldJson,
 }
 
-impl fmt::Debug for MessageLookup {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Receipt {}", serde_json::to_string(&self.receipt).unwrap())?;
-        write!(f, "Tipset {:?}", self.tipset)?;
-        write!(f, "Height {}", self.height)?;
-        write!(f, "Message {:?}", self.message)?;
-        write!(f, "ReturnDec {:?}", serde_json::to_string(&self.return_dec).unwrap())
-    }
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HeadChange {
+    #[serde(rename = "Type")]
+    pub change_type: HeadChangeType,
+    pub val: Tipset,
 }
 
 #[derive(Error, Debug)]
@@ -53,50 +69,654 @@ pub enum StateError {
     ParseReturnDecError(#[from] serde_json::Error),
     #[error("convert return_dec to target error: {0}")]
     ConvertReturnDecError(String),
-    #[error("parse by yourself {0}")]
-    ParseByYourSelf(String),
+    #[error("decode return base64 error: {0}")]
+    DecodeReturnBase64Error(#[from] base64::DecodeError),
+    #[error("decode return cbor error: {0}")]
+    DecodeReturnCborError(String),
+    #[error("message returns nothing")]
+    EmptyReturn,
     #[error("parse address error {0}")]
     ParseAddressError(#[from] fvm_shared::address::Error),
+    #[error("unknown address {0}")]
+    UnknownAddress(AnyAddress),
+    #[error("wait message {0} timeout")]
+    WaitTimeout(String),
+    #[error("wait message {0} cancelled")]
+    WaitCancelled(String),
 }
 
-pub async fn wait_msg<T: FromStr + Default>(rpc: RpcEndpoint, cid: CidJson) -> Result<T, StateError>
-where
-    <T as FromStr>::Err: Debug,
-{
-    let msg_lookup = rpc.post::<_, MessageLookup>(state_api::STATE_WAIT_MSG, json!([cid, 10])).await?;
+pub trait ReturnDecode: Sized {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError>;
 
-    if msg_lookup.receipt.exit_code != ExitCode::OK {
-        return Err(StateError::MsgCodeError(msg_lookup.receipt.exit_code));
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError>;
+
+    fn decode_empty() -> Result<Self, StateError> {
+        Err(StateError::EmptyReturn)
+    }
+}
+
+pub fn decode_json<T: DeserializeOwned>(ret: serde_json::Value) -> Result<T, StateError> {
+    match serde_json::from_value::<T>(ret) {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(StateError::ConvertReturnDecError(err.to_string())),
+    }
+}
+
+pub fn decode_cbor<T: DeserializeOwned>(ret: &[u8]) -> Result<T, StateError> {
+    match fvm_ipld_encoding::from_slice::<T>(ret) {
+        Ok(ret) => Ok(ret),
+        Err(err) => Err(StateError::DecodeReturnCborError(err.to_string())),
+    }
+}
+
+impl ReturnDecode for () {
+    fn decode_json(_ret: serde_json::Value) -> Result<Self, StateError> {
+        Ok(())
+    }
+
+    fn decode_cbor(_ret: &[u8]) -> Result<Self, StateError> {
+        Ok(())
     }
 
-    match msg_lookup.return_dec {
-        IpldJson(Ipld::Null) => match msg_lookup.receipt.return_data {
-            Some(s) => {
-                warn!("> Cannot parse {}, pass to upper", &s);
-                Err(StateError::ParseByYourSelf(s))
+    fn decode_empty() -> Result<Self, StateError> {
+        Ok(())
+    }
+}
+
+impl ReturnDecode for serde_json::Value {
+    fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+        Ok(ret)
+    }
+
+    fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+        Ok(serde_json::to_value(IpldJson(decode_cbor::<Ipld>(ret)?))?)
+    }
+
+    fn decode_empty() -> Result<Self, StateError> {
+        Ok(serde_json::Value::Null)
+    }
+}
+
+// Lotus only fills ReturnDec when it knows the actor abi, so fall back to the raw cbor Return
+pub fn decode_return<T: ReturnDecode>(return_dec: IpldJson, return_data: Option<String>) -> Result<T, StateError> {
+    let return_data = match return_data {
+        Some(s) => base64::decode_config(s, base64::STANDARD)?,
+        None => Vec::new(),
+    };
+
+    match return_dec {
+        IpldJson(Ipld::Null) if return_data.is_empty() => T::decode_empty(),
+        IpldJson(Ipld::Null) => T::decode_cbor(&return_data),
+        IpldJson(ipld) => match T::decode_json(serde_json::to_value(IpldJson(ipld))?) {
+            Ok(ret) => Ok(ret),
+            Err(err) if !return_data.is_empty() => {
+                warn!("> Cannot decode return_dec: {}, decode cbor return", err);
+                T::decode_cbor(&return_data)
             }
-            None => Ok(T::default()),
+            Err(err) => Err(err),
         },
-        IpldJson(ipld) => {
-            let ret = serde_json::to_string(&ipld)?;
-            match T::from_str(&ret) {
-                Ok(ret) => Ok(ret),
-                Err(err) => Err(StateError::ConvertReturnDecError(format!("{:?}", err))),
+    }
+}
+
+#[derive(Debug)]
+pub struct MsgReceipt<T> {
+    pub value: T,
+    pub pushed: CidJson,
+    pub executed: CidJson,
+    pub height: i64,
+    pub tipset: TipsetKeysJson,
+    pub gas_used: i64,
+}
+
+impl<T> MsgReceipt<T> {
+    pub fn is_replaced(&self) -> bool {
+        self.pushed.0 != self.executed.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WaitOptions {
+    pub confidence: i64,
+    pub lookback: i64,
+    pub allow_replaced: bool,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancellationToken>,
+    pub poll_interval: Duration,
+}
+
+impl Default for WaitOptions {
+    fn default() -> Self {
+        Self {
+            confidence: DEFAULT_CONFIDENCE,
+            lookback: LOOKBACK_NO_LIMIT,
+            allow_replaced: true,
+            deadline: None,
+            cancel: None,
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl WaitOptions {
+    pub fn finality() -> Self {
+        Self { confidence: FINALITY, ..Default::default() }
+    }
+
+    pub fn confidence(mut self, confidence: i64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn lookback(mut self, lookback: i64) -> Self {
+        self.lookback = lookback;
+        self
+    }
+
+    pub fn allow_replaced(mut self, allow_replaced: bool) -> Self {
+        self.allow_replaced = allow_replaced;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    pub fn cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+// Searches again on every head change when the node pushes them, otherwise every poll interval
+async fn search_msg<A: LotusApi>(
+    api: A,
+    cid: CidJson,
+    opts: &WaitOptions,
+    mut notify: Option<Subscription<Vec<HeadChange>>>,
+) -> Result<MessageLookup, StateError> {
+    let CidJson(cid) = cid;
+    loop {
+        match api.state_search_msg(cid, opts.lookback, opts.allow_replaced).await {
+            Ok(Some(msg_lookup)) => {
+                let height = api.chain_head_height().await?;
+                if height - msg_lookup.height >= opts.confidence {
+                    return Ok(msg_lookup);
+                }
+                info!(
+                    "> Message {} executed at {}, head {}, wait {} confidence",
+                    cid, msg_lookup.height, height, opts.confidence
+                );
+            }
+            Ok(None) => info!("> Message {} not found on chain yet", cid),
+            Err(err) if err.is_transient() => warn!("> Search message {} fail: {}, retry", cid, err),
+            Err(err) => return Err(StateError::StateRpcError(err)),
+        }
+
+        match notify.as_mut() {
+            Some(sub) => {
+                if sub.next().await.is_none() {
+                    warn!("> Chain notify closed, poll message {}", cid);
+                    notify = None;
+                }
             }
+            None => tokio::time::sleep(opts.poll_interval).await,
         }
     }
 }
 
-pub async fn lookup_id(rpc: RpcEndpoint, addr: Address) -> Result<Address, StateError> {
-    // state_api::STATE_LOOKUP_ID definition is wrong
-    match rpc
-        .post::<_, String>(
-            /* state_api::STATE_LOOKUP_ID */ "Filecoin.StateLookupID",
-            json!([addr.to_string(), []]),
-        )
-        .await
-    {
-        Ok(addr) => Ok(Address::from_str(&addr)?),
+// StateWaitMsg blocks for the whole http timeout, so follow head changes when the node pushes them and
+// fall back to polling StateSearchMsg once a blocking wait breaks
+pub async fn wait_msg_lookup<A: LotusApi>(
+    api: A,
+    cid: CidJson,
+    opts: &WaitOptions,
+) -> Result<MessageLookup, StateError> {
+    let CidJson(inner) = cid.clone();
+
+    let wait = async {
+        if let Ok(notify) = chain_notify(api.clone()).await {
+            return search_msg(api.clone(), cid, opts, Some(notify)).await;
+        }
+        // A deadline or a long confidence outlives any http timeout, blocking in StateWaitMsg only delays the search
+        if opts.deadline.is_some() || opts.confidence > DEFAULT_CONFIDENCE {
+            return search_msg(api.clone(), cid, opts, None).await;
+        }
+        match api.state_wait_msg(inner, opts.confidence, opts.lookback, opts.allow_replaced).await {
+            Ok(msg_lookup) => Ok(msg_lookup),
+            Err(err) if err.is_transient() => {
+                warn!("> Wait message {} fail: {}, fall back to search", inner, err);
+                search_msg(api.clone(), cid, opts, None).await
+            }
+            Err(err) => Err(StateError::StateRpcError(err)),
+        }
+    };
+    let deadline = async {
+        match opts.deadline {
+            Some(deadline) => tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await,
+            None => std::future::pending().await,
+        }
+    };
+    let cancelled = async {
+        match &opts.cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        res = wait => res,
+        _ = deadline => Err(StateError::WaitTimeout(inner.to_string())),
+        _ = cancelled => Err(StateError::WaitCancelled(inner.to_string())),
+    }
+}
+
+pub async fn wait_msg<A: LotusApi, T: ReturnDecode>(api: A, cid: CidJson) -> Result<T, StateError> {
+    wait_msg_with(api, cid, WaitOptions::default()).await
+}
+
+pub async fn wait_msg_with<A: LotusApi, T: ReturnDecode>(
+    api: A,
+    cid: CidJson,
+    opts: WaitOptions,
+) -> Result<T, StateError> {
+    Ok(wait_msg_receipt(api, cid, opts).await?.value)
+}
+
+pub async fn wait_msg_receipt<A: LotusApi, T: ReturnDecode>(
+    api: A,
+    cid: CidJson,
+    opts: WaitOptions,
+) -> Result<MsgReceipt<T>, StateError> {
+    let msg_lookup = wait_msg_lookup(api, cid.clone(), &opts).await?;
+
+    let CidJson(pushed) = cid;
+    let CidJson(executed) = msg_lookup.message;
+    if pushed != executed {
+        warn!("> Message {} was replaced by {} at {}", pushed, executed, msg_lookup.height);
+    }
+
+    if msg_lookup.receipt.exit_code != ExitCode::OK {
+        return Err(StateError::MsgCodeError(msg_lookup.receipt.exit_code));
+    }
+
+    let value = decode_return::<T>(msg_lookup.return_dec, msg_lookup.receipt.return_data)?;
+
+    Ok(MsgReceipt {
+        value,
+        pushed: CidJson(pushed),
+        executed: CidJson(executed),
+        height: msg_lookup.height,
+        tipset: msg_lookup.tipset,
+        gas_used: msg_lookup.receipt.gas_used,
+    })
+}
+
+pub async fn network_name<A: LotusApi>(api: A) -> Result<String, StateError> {
+    match api.state_network_name().await {
+        Ok(res) => Ok(res),
         Err(err) => Err(StateError::StateRpcError(err)),
     }
 }
+
+pub async fn lookup_id<A: LotusApi, T: Into<AnyAddress>>(api: A, addr: T) -> Result<Address, StateError> {
+    let addr = addr.into();
+    match AddressResolver::new(api).id(&addr).await {
+        Ok(id) => Ok(id),
+        Err(RpcError::LotusError(LotusError::ActorNotFound(_))) => Err(StateError::UnknownAddress(addr)),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn robust_address<A: LotusApi, T: Into<AnyAddress>>(api: A, addr: T) -> Result<AnyAddress, StateError> {
+    let addr = addr.into();
+    match AddressResolver::new(api).robust(&addr).await {
+        Ok(robust) => Ok(robust),
+        Err(RpcError::LotusError(LotusError::ActorNotFound(_))) => Err(StateError::UnknownAddress(addr)),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn eth_address<A: LotusApi, T: Into<AnyAddress>>(api: A, addr: T) -> Result<AnyAddress, StateError> {
+    let addr = addr.into();
+    match AddressResolver::new(api).eth(&addr).await {
+        Ok(eth) => Ok(AnyAddress::Eth(eth)),
+        Err(RpcError::LotusError(LotusError::ActorNotFound(_))) => Err(StateError::UnknownAddress(addr)),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_info<A: LotusApi>(api: A, miner: Address) -> Result<MinerInfo, StateError> {
+    match api.state_miner_info(miner).await {
+        Ok(info) => Ok(info),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_power<A: LotusApi>(api: A, miner: Address) -> Result<MinerPower, StateError> {
+    match api.state_miner_power(miner).await {
+        Ok(power) => Ok(power),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_available_balance<A: LotusApi>(api: A, miner: Address) -> Result<TokenAmount, StateError> {
+    match api.state_miner_available_balance(miner).await {
+        Ok(balance) => Ok(balance),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_faults<A: LotusApi>(api: A, miner: Address) -> Result<Vec<u64>, StateError> {
+    match api.state_miner_faults(miner).await {
+        Ok(faults) => Ok(faults),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_proving_deadline<A: LotusApi>(api: A, miner: Address) -> Result<DeadlineInfo, StateError> {
+    match api.state_miner_proving_deadline(miner).await {
+        Ok(deadline) => Ok(deadline),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+pub async fn miner_sector_count<A: LotusApi>(api: A, miner: Address) -> Result<MinerSectors, StateError> {
+    match api.state_miner_sector_count(miner).await {
+        Ok(sectors) => Ok(sectors),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+// Re-executes the message on its parent state, the trace shows which internal send failed
+pub async fn replay_msg<A: LotusApi>(api: A, cid: CidJson) -> Result<ExecutionTrace, StateError> {
+    match api.state_replay(cid.0).await {
+        Ok(res) => Ok(res.execution_trace),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ActorState<T> {
+    pub code: Cid,
+    pub head: Cid,
+    pub balance: TokenAmount,
+    pub state: T,
+}
+
+// StateReadState only renders builtin actors, custom actors fall back to the raw cbor head object
+pub async fn read_actor_state<A: LotusApi, T: ReturnDecode>(
+    api: A,
+    actor_address: Address,
+) -> Result<ActorState<T>, StateError> {
+    let actor = match api.state_get_actor(actor_address).await {
+        Ok(actor) => actor,
+        Err(err) => return Err(StateError::StateRpcError(err)),
+    };
+
+    match api.state_read_state(actor_address).await {
+        // Newer nodes answer with an empty state instead of an error, it would decode as a null value
+        Ok(res) if res.state.is_null() => warn!("> No state rendered for {}, decode cbor head", actor_address),
+        Ok(res) => match T::decode_json(res.state) {
+            Ok(state) => return Ok(ActorState { code: actor.code.0, head: actor.head.0, balance: res.balance, state }),
+            Err(err) => warn!("> Cannot decode state of {}: {}, decode cbor head", actor_address, err),
+        },
+        Err(err) => warn!("> Cannot read state of {}: {}, decode cbor head", actor_address, err),
+    }
+
+    let head = match api.chain_read_obj(actor.head.0).await {
+        Ok(head) => head,
+        Err(err) => return Err(StateError::StateRpcError(err)),
+    };
+
+    Ok(ActorState { code: actor.code.0, head: actor.head.0, balance: actor.balance, state: T::decode_cbor(&head)? })
+}
+
+#[derive(Clone, Debug)]
+pub struct MinerSnapshot {
+    pub info: MinerInfo,
+    pub power: MinerPower,
+    pub available_balance: TokenAmount,
+    pub sectors: MinerSectors,
+}
+
+pub async fn miner_snapshot<A: LotusApi>(api: A, miner: Address) -> Result<MinerSnapshot, StateError> {
+    Ok(MinerSnapshot {
+        info: miner_info(api.clone(), miner).await?,
+        power: miner_power(api.clone(), miner).await?,
+        available_balance: miner_available_balance(api.clone(), miner).await?,
+        sectors: miner_sector_count(api, miner).await?,
+    })
+}
+
+pub async fn chain_notify<A: LotusApi>(api: A) -> Result<Subscription<Vec<HeadChange>>, StateError> {
+    match api.chain_notify().await {
+        Ok(sub) => Ok(sub.cast()),
+        Err(err) => Err(StateError::StateRpcError(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_ipld_encoding::{
+        tuple::{Deserialize_tuple, Serialize_tuple},
+        Cbor,
+        RawBytes,
+    };
+    use fvm_shared::{bigint::BigInt, message::Message};
+    use lotusmock::{MockApi, MockMiner, MockReceipt};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    // Never pushed to the mock, a wait on it only ends by deadline or cancellation
+    const MISSING_CID: &str = "bafy2bzacea3wsdh6y3a36tb3skempjoxqpuyompjbmfeyf34fi3uy6uue42v4";
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Created(u64, String);
+
+    impl ReturnDecode for Created {
+        fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+            decode_json(ret)
+        }
+
+        fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+            decode_cbor(ret)
+        }
+    }
+
+    #[derive(Serialize_tuple, Deserialize_tuple, Debug, PartialEq)]
+    struct OwnerState {
+        fee: u64,
+        miners: Vec<u64>,
+    }
+    impl Cbor for OwnerState {}
+
+    impl ReturnDecode for OwnerState {
+        fn decode_json(ret: serde_json::Value) -> Result<Self, StateError> {
+            decode_json(ret)
+        }
+
+        fn decode_cbor(ret: &[u8]) -> Result<Self, StateError> {
+            decode_cbor(ret)
+        }
+    }
+
+    fn transfer(from: Address, sequence: u64, gas_premium: u64) -> Message {
+        Message {
+            version: 0,
+            to: Address::new_id(1901),
+            from,
+            sequence,
+            value: TokenAmount::from_atto(1),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from_atto(200_000),
+            gas_premium: TokenAmount::from_atto(gas_premium),
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_ends_a_pending_wait() {
+        let api = MockApi::new();
+        let cid = Cid::from_str(MISSING_CID).unwrap();
+        let cancel = CancellationToken::new();
+        let opts = WaitOptions::default()
+            .timeout(Duration::from_secs(60))
+            .poll_interval(Duration::from_millis(10))
+            .cancel(cancel.clone());
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let start = Instant::now();
+        assert!(matches!(
+            wait_msg_with::<_, ()>(api, CidJson(cid), opts).await,
+            Err(StateError::WaitCancelled(pending)) if pending == cid.to_string()
+        ));
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn replaced_message_returns_the_executing_cid() {
+        let api = MockApi::new();
+        let from = Address::new_id(1900);
+        let chain = api.chain();
+        let (pushed, replacement) = {
+            let mut chain = chain.lock().unwrap();
+            chain.set_balance(from, TokenAmount::from_whole(10));
+            chain.hold();
+            let pushed = chain.push_message(transfer(from, 0, 100_000)).unwrap();
+            let replacement = chain.push_message(transfer(from, 0, 200_000)).unwrap();
+            chain.release();
+            (pushed, replacement)
+        };
+
+        let receipt =
+            wait_msg_receipt::<_, ()>(api, CidJson(pushed), WaitOptions::default().confidence(0)).await.unwrap();
+        assert!(receipt.is_replaced());
+        assert_eq!((receipt.pushed.0, receipt.executed.0), (pushed, replacement));
+        assert_eq!(receipt.height, chain.lock().unwrap().height());
+    }
+
+    #[test]
+    fn raw_return_decodes_as_cbor() {
+        let ret = base64::encode(fvm_ipld_encoding::to_vec(&(7u64, "peggy")).unwrap());
+        let value = decode_return::<serde_json::Value>(IpldJson(Ipld::Null), Some(ret.clone())).unwrap();
+        assert_eq!(value, json!([7, "peggy"]));
+        assert_eq!(decode_return::<Created>(IpldJson(Ipld::Null), Some(ret)).unwrap(), Created(7, "peggy".to_string()));
+        assert!(matches!(decode_return::<Created>(IpldJson(Ipld::Null), None), Err(StateError::EmptyReturn)));
+    }
+
+    #[tokio::test]
+    async fn wait_decodes_the_cbor_return_when_return_dec_does_not_fit() {
+        let api = MockApi::new();
+        let from = Address::new_id(1910);
+        let ret = fvm_ipld_encoding::to_vec(&(7u64, "peggy")).unwrap();
+        let chain = api.chain();
+        let (raw, undecodable) = {
+            let mut chain = chain.lock().unwrap();
+            chain.set_balance(from, TokenAmount::from_whole(10));
+            chain.script(Address::new_id(1901), 0, MockReceipt::default().with_return_data(ret.clone()));
+            chain.script(
+                Address::new_id(1901),
+                0,
+                MockReceipt::default().with_return_data(ret).with_return_dec(json!({"Unknown": true})),
+            );
+            (
+                chain.push_message(transfer(from, 0, 100_000)).unwrap(),
+                chain.push_message(transfer(from, 1, 100_000)).unwrap(),
+            )
+        };
+
+        let expected = Created(7, "peggy".to_string());
+        assert_eq!(wait_msg::<_, Created>(api.clone(), CidJson(raw)).await.unwrap(), expected);
+        assert_eq!(wait_msg::<_, Created>(api, CidJson(undecodable)).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn miner_queries_read_the_miner_state() {
+        let api = MockApi::new();
+        let (miner, owner, worker) = (Address::new_id(1920), Address::new_id(1921), Address::new_id(1922));
+        let info = MinerInfo {
+            owner,
+            worker,
+            new_worker: None,
+            control_addresses: vec![Address::new_id(1923)],
+            worker_change_epoch: -1,
+            peer_id: None,
+            window_post_proof_type: 9,
+            sector_size: 32 << 30,
+            window_post_partition_sectors: 2349,
+            consensus_fault_elapsed: -1,
+            beneficiary: Some(owner),
+            beneficiary_term: None,
+            pending_owner_address: None,
+        };
+        let power = MinerPower {
+            miner_power: Claim {
+                raw_byte_power: BigInt::from(32u64 << 30),
+                quality_adj_power: BigInt::from(320u64 << 30),
+            },
+            has_min_power: true,
+            ..Default::default()
+        };
+        let chain = api.chain();
+        {
+            let mut chain = chain.lock().unwrap();
+            chain.set_miner(miner, MockMiner {
+                info,
+                power,
+                faults: vec![6, 3, 5],
+                sectors: MinerSectors { live: 10, active: 7, faulty: 3 },
+            });
+            chain.set_balance(miner, TokenAmount::from_whole(42));
+            chain.advance(100);
+        }
+
+        let snapshot = miner_snapshot(api.clone(), miner).await.unwrap();
+        assert_eq!(
+            (snapshot.info.owner, snapshot.info.worker, snapshot.info.beneficiary),
+            (owner, worker, Some(owner))
+        );
+        assert_eq!(snapshot.info.control_addresses, vec![Address::new_id(1923)]);
+        assert_eq!(snapshot.power.miner_power.quality_adj_power, BigInt::from(320u64 << 30));
+        assert!(snapshot.power.has_min_power);
+        assert_eq!(snapshot.available_balance, TokenAmount::from_whole(42));
+        assert_eq!((snapshot.sectors.live, snapshot.sectors.active, snapshot.sectors.faulty), (10, 7, 3));
+
+        assert_eq!(miner_faults(api.clone(), miner).await.unwrap(), vec![3, 5, 6]);
+        let deadline = miner_proving_deadline(api.clone(), miner).await.unwrap();
+        assert_eq!(deadline.current_epoch, 100);
+        assert!(deadline.open <= 100 && 100 < deadline.close);
+
+        assert!(matches!(miner_info(api, Address::new_id(1929)).await, Err(StateError::StateRpcError(_))));
+    }
+
+    #[tokio::test]
+    async fn custom_actor_state_is_read_from_the_head_object() {
+        let api = MockApi::new();
+        let code = Cid::from_str(MISSING_CID).unwrap();
+        let state = OwnerState { fee: 5, miners: vec![1931, 1932] };
+        let actor = {
+            let chain = api.chain();
+            let mut chain = chain.lock().unwrap();
+            let actor = chain.set_actor_state(Address::new_id(1930), code, &state);
+            chain.set_balance(actor, TokenAmount::from_whole(3));
+            actor
+        };
+
+        let read = read_actor_state::<_, OwnerState>(api.clone(), actor).await.unwrap();
+        assert_eq!((read.code, read.balance, read.state), (code, TokenAmount::from_whole(3), state));
+        // An untyped read must not stop at the empty rendered state
+        let read = read_actor_state::<_, serde_json::Value>(api, actor).await.unwrap();
+        assert_eq!(read.state, json!([5, [1931, 1932]]));
+    }
+}
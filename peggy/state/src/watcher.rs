@@ -0,0 +1,296 @@
+use cid::Cid;
+use log::warn;
+use lotusapi::{LotusApi, TipsetJson};
+use rpc::Subscription;
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{chain_notify, HeadChange, HeadChangeType, StateError, FINALITY};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TipsetRef {
+    pub key: Vec<Cid>,
+    pub height: i64,
+}
+
+impl From<&TipsetJson> for TipsetRef {
+    fn from(tipset: &TipsetJson) -> Self {
+        Self { key: tipset.key(), height: tipset.height }
+    }
+}
+
+impl From<&HeadChange> for TipsetRef {
+    fn from(change: &HeadChange) -> Self {
+        Self { key: change.val.cids.0.cids().to_vec(), height: change.val.height }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainEvent {
+    Apply(TipsetRef),
+    Revert(TipsetRef),
+    Finalized(TipsetRef),
+}
+
+pub struct ChainWatcher<A> {
+    api: A,
+    notify: Option<Subscription<Vec<HeadChange>>>,
+    subscribed: bool,
+    finality: i64,
+    poll_interval: Duration,
+    // Applied tipsets not final yet, from the oldest to the head
+    chain: VecDeque<TipsetRef>,
+    events: VecDeque<ChainEvent>,
+}
+
+impl<A: LotusApi> ChainWatcher<A> {
+    pub fn new(api: A) -> Self {
+        Self {
+            api,
+            notify: None,
+            subscribed: false,
+            finality: FINALITY,
+            poll_interval: Duration::from_secs(5),
+            chain: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    // Follow ChainNotify instead of polling ChainHead, polling takes over if the subscription closes
+    pub fn notify(mut self, notify: Subscription<Vec<HeadChange>>) -> Self {
+        self.notify = Some(notify);
+        self.subscribed = true;
+        self
+    }
+
+    pub fn finality(mut self, epochs: i64) -> Self {
+        self.finality = epochs.max(1);
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn head(&self) -> Option<&TipsetRef> {
+        self.chain.back()
+    }
+
+    pub async fn next(&mut self) -> Result<ChainEvent, StateError> {
+        // Subscribe once when the endpoint supports it, a closed subscription is not reopened
+        if !self.subscribed {
+            self.subscribed = true;
+            if let Ok(notify) = chain_notify(self.api.clone()).await {
+                self.notify = Some(notify);
+            }
+        }
+
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            match self.notify.as_mut() {
+                Some(notify) => match notify.next().await {
+                    Some(Ok(changes)) => {
+                        for change in changes {
+                            self.on_head_change(change);
+                        }
+                    }
+                    Some(Err(err)) => return Err(StateError::StateRpcError(err)),
+                    None => {
+                        warn!("> Chain notify closed, poll chain head");
+                        self.notify = None;
+                    }
+                },
+                None => self.poll().await?,
+            }
+        }
+    }
+
+    fn on_head_change(&mut self, change: HeadChange) {
+        let tipset = TipsetRef::from(&change);
+        match change.change_type {
+            HeadChangeType::Current if self.head() == Some(&tipset) => {}
+            HeadChangeType::Current | HeadChangeType::Apply => self.apply(tipset),
+            HeadChangeType::Revert => {
+                if self.head() == Some(&tipset) {
+                    self.chain.pop_back();
+                } else {
+                    warn!("> Revert untracked tipset at {}", tipset.height);
+                }
+                self.events.push_back(ChainEvent::Revert(tipset));
+            }
+        }
+    }
+
+    // Walk back from the new head until a tracked tipset, everything above that tipset was reorged out
+    async fn poll(&mut self) -> Result<(), StateError> {
+        let mut tipset = match self.api.chain_head().await {
+            Ok(head) => head,
+            Err(err) => return Err(StateError::StateRpcError(err)),
+        };
+
+        if self.head().map(|head| head.key == tipset.key()).unwrap_or(false) {
+            tokio::time::sleep(self.poll_interval).await;
+            return Ok(());
+        }
+
+        let mut applied = Vec::new();
+        loop {
+            let current = TipsetRef::from(&tipset);
+            if self.chain.is_empty() {
+                applied.push(current);
+                break;
+            }
+
+            if let Some(pos) = self.chain.iter().position(|t| t.key == current.key) {
+                while self.chain.len() > pos + 1 {
+                    let reverted = self.chain.pop_back().unwrap();
+                    self.events.push_back(ChainEvent::Revert(reverted));
+                }
+                break;
+            }
+
+            // The fork point is below everything tracked, drop the whole chain and start again from the fork
+            let oldest = self.chain.front().unwrap().height;
+            if current.height <= oldest {
+                warn!("> Reorg below tracked tipset at {}, re-anchor at {}", oldest, current.height);
+                while let Some(reverted) = self.chain.pop_back() {
+                    self.events.push_back(ChainEvent::Revert(reverted));
+                }
+                applied.push(current);
+                break;
+            }
+
+            applied.push(current);
+            tipset = match self.api.chain_get_tipset(tipset.parents()).await {
+                Ok(parent) => parent,
+                Err(err) => return Err(StateError::StateRpcError(err)),
+            };
+        }
+
+        for tipset in applied.into_iter().rev() {
+            self.apply(tipset);
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, tipset: TipsetRef) {
+        let height = tipset.height;
+        self.chain.push_back(tipset.clone());
+        self.events.push_back(ChainEvent::Apply(tipset));
+
+        while self.chain.len() > 1 && self.chain.front().unwrap().height <= height - self.finality {
+            let finalized = self.chain.pop_front().unwrap();
+            self.events.push_back(ChainEvent::Finalized(finalized));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeadChange;
+    use forest_json::cid::CidJson;
+    use lotusmock::MockApi;
+    use serde_json::json;
+
+    fn watcher(api: &MockApi) -> ChainWatcher<MockApi> {
+        ChainWatcher::new(api.clone()).poll_interval(Duration::from_millis(1))
+    }
+
+    async fn next_events(watcher: &mut ChainWatcher<MockApi>, count: usize) -> Vec<ChainEvent> {
+        let mut events = Vec::new();
+        for _ in 0..count {
+            events.push(watcher.next().await.unwrap());
+        }
+        events
+    }
+
+    fn head_change(change_type: &str, tipset: &TipsetRef) -> HeadChange {
+        let cids = tipset.key.iter().cloned().map(CidJson).collect::<Vec<_>>();
+        serde_json::from_value(json!({"Type": change_type, "Val": {"Cids": cids, "Height": tipset.height}})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn poll_reverts_reorged_tipsets_before_applying_the_fork() {
+        let api = MockApi::new();
+        api.chain().lock().unwrap().advance(3);
+        let mut watcher = watcher(&api);
+
+        let events = next_events(&mut watcher, 1).await;
+        assert!(matches!(&events[..], [ChainEvent::Apply(t)] if t.height == 3));
+
+        api.chain().lock().unwrap().advance(2);
+        let applied = next_events(&mut watcher, 2).await;
+        let (old4, old5) = match &applied[..] {
+            [ChainEvent::Apply(t4), ChainEvent::Apply(t5)] => (t4.clone(), t5.clone()),
+            events => panic!("unexpected events {:?}", events),
+        };
+        assert_eq!((old4.height, old5.height), (4, 5));
+
+        api.chain().lock().unwrap().reorg(2);
+        let events = next_events(&mut watcher, 4).await;
+        assert_eq!(events[0], ChainEvent::Revert(old5.clone()));
+        assert_eq!(events[1], ChainEvent::Revert(old4.clone()));
+        match &events[2..] {
+            [ChainEvent::Apply(new4), ChainEvent::Apply(new5)] => {
+                assert_eq!((new4.height, new5.height), (4, 5));
+                assert_ne!(new4.key, old4.key);
+                assert_ne!(new5.key, old5.key);
+                assert_eq!(watcher.head(), Some(new5));
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_re_anchors_after_reorg_below_tracked_chain() {
+        let api = MockApi::new();
+        api.chain().lock().unwrap().advance(3);
+        let mut watcher = watcher(&api);
+        let old3 = match &next_events(&mut watcher, 1).await[..] {
+            [ChainEvent::Apply(t)] => t.clone(),
+            events => panic!("unexpected events {:?}", events),
+        };
+
+        // Only the first head is tracked, a 1 epoch reorg already forks below it
+        api.chain().lock().unwrap().reorg(1);
+        let events = next_events(&mut watcher, 2).await;
+        assert_eq!(events[0], ChainEvent::Revert(old3.clone()));
+        match &events[1..] {
+            [ChainEvent::Apply(new3)] => {
+                assert_eq!(new3.height, 3);
+                assert_ne!(new3.key, old3.key);
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+
+        api.chain().lock().unwrap().advance(1);
+        let events = next_events(&mut watcher, 1).await;
+        assert!(matches!(&events[..], [ChainEvent::Apply(t)] if t.height == 4));
+        assert_eq!(watcher.head().map(|head| head.height), Some(4));
+    }
+
+    #[tokio::test]
+    async fn notify_revert_pops_the_head() {
+        let api = MockApi::new();
+        api.chain().lock().unwrap().advance(2);
+        let head = TipsetRef::from(&api.chain_head().await.unwrap());
+        let parent = TipsetRef::from(&api.chain_get_tipset(api.chain_head().await.unwrap().parents()).await.unwrap());
+        let mut watcher = watcher(&api);
+
+        watcher.on_head_change(head_change("current", &parent));
+        watcher.on_head_change(head_change("apply", &head));
+        watcher.on_head_change(head_change("revert", &head));
+
+        assert_eq!(watcher.head(), Some(&parent));
+        let events = watcher.events.drain(..).collect::<Vec<_>>();
+        assert_eq!(events, vec![
+            ChainEvent::Apply(parent.clone()),
+            ChainEvent::Apply(head.clone()),
+            ChainEvent::Revert(head)
+        ]);
+    }
+}
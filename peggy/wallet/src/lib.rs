@@ -1,8 +1,8 @@
 use forest_key_management::{json::KeyInfoJson, Key};
-use forest_rpc_api::wallet_api;
 use fvm_shared::{address::Address, crypto::signature::SignatureType, econ::TokenAmount};
-use num_bigint::{BigInt, ParseBigIntError};
-use rpc::{RpcEndpoint, RpcError};
+use lotusapi::LotusApi;
+use num_bigint::ParseBigIntError;
+use rpc::RpcError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -21,13 +21,17 @@ pub fn create_wallet(wallet_type: SignatureType) -> (Address, String, Key, KeyIn
     (key.address, encoded_key, key, key_info)
 }
 
-pub async fn get_balance(rpc: RpcEndpoint, address: Address) -> Result<TokenAmount, WalletError> {
-    match rpc.post::<_, String>(wallet_api::WALLET_BALANCE, vec![address.to_string()]).await {
-        Ok(res) => {
-            let res = res.parse::<BigInt>()?;
-            let res = TokenAmount::from_atto(res);
-            Ok(res)
-        }
+pub async fn get_balance<A: LotusApi>(api: A, address: Address) -> Result<TokenAmount, WalletError> {
+    match api.wallet_balance(address).await {
+        Ok(res) => Ok(res),
         Err(err) => Err(WalletError::RpcRequestError(err)),
     }
 }
+
+pub async fn get_balances<A: LotusApi>(
+    api: A,
+    addresses: Vec<Address>,
+) -> Result<Vec<Result<TokenAmount, WalletError>>, WalletError> {
+    let balances = api.wallet_balances(addresses).await?;
+    Ok(balances.into_iter().map(|res| res.map_err(WalletError::RpcRequestError)).collect())
+}